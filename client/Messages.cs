@@ -204,15 +204,13 @@ internal class Messages : IDisposable {
     }
 
     private async Task DownloadMessages(uint world, uint territory, ushort? ward, ushort? plot) {
-        var route = $"/messages/{territory}";
+        var route = $"/messages/{territory}?world={world}";
         if (ward != null) {
-            route += $"?ward={ward}";
+            route += $"&ward={ward}";
 
             if (plot != null) {
                 route += $"&plot={plot}";
             }
-
-            route += $"&world={world}";
         }
 
         var resp = await ServerHelper.SendRequest(
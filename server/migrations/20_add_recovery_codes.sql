@@ -0,0 +1,3 @@
+alter table users
+    add column recovery text default null;
+create index users_recovery_idx on users (recovery);
@@ -0,0 +1,24 @@
+create table auth_tokens
+(
+    id      integer   not null primary key autoincrement,
+    user    integer   not null references users (id) on delete cascade,
+    hash    text      not null unique,
+    label   text      not null,
+    created timestamp not null default current_timestamp
+);
+create index auth_tokens_user_idx on auth_tokens (user);
+
+insert into auth_tokens (user, hash, label)
+select id, auth, 'default'
+from users;
+
+alter table users
+    drop column auth;
+
+create table link_codes
+(
+    hash    text      not null primary key,
+    user    integer   not null references users (id) on delete cascade,
+    expires timestamp not null
+);
+create index link_codes_user_idx on link_codes (user);
@@ -0,0 +1,3 @@
+delete
+from votes
+where exists(select 1 from messages m where m.id = votes.message and m.user = votes.user);
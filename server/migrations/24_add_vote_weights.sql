@@ -0,0 +1,7 @@
+alter table votes
+    add column weight float not null default 1;
+alter table votes
+    add column nullified boolean not null default false;
+
+alter table users
+    add column vote_flagged boolean not null default false;
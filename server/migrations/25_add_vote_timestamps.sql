@@ -0,0 +1,3 @@
+alter table votes
+    add column created timestamp default null;
+create index votes_created_idx on votes (created);
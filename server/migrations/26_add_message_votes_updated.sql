@@ -0,0 +1,2 @@
+alter table messages
+    add column votes_updated timestamp default null;
@@ -0,0 +1,4 @@
+alter table messages
+    add column pack text default null;
+create index messages_pack_idx on messages (pack);
+create index messages_created_idx on messages (created);
@@ -0,0 +1 @@
+create index messages_territory_x_z_idx on messages (territory, x, z);
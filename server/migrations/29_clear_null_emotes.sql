@@ -0,0 +1,5 @@
+-- emotes are now stored in a binary encoding, with older rows left as json.
+-- a json null is just a missing emote
+update messages
+set emote = null
+where emote = 'null';
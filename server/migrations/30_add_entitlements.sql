@@ -0,0 +1,10 @@
+alter table extra_tokens
+    add column entitlement text default null;
+
+create table user_entitlements
+(
+    user        integer not null references users (id) on delete cascade,
+    entitlement text    not null,
+
+    primary key (user, entitlement)
+);
@@ -0,0 +1,66 @@
+-- vote tallies kept up to date by triggers so that ranking messages does
+-- not need to aggregate every vote. the triggers delete and insert the tally
+-- since "insert or replace" is overridden by the upsert that records votes
+create table message_scores
+(
+    message        text    not null primary key,
+    positive_votes integer not null,
+    negative_votes integer not null,
+    score          float   not null
+);
+create index message_scores_score_idx on message_scores (score);
+
+insert into message_scores (message, positive_votes, negative_votes, score)
+select v.message,
+       count(case when v.vote > 0 and not v.nullified then 1 end),
+       count(case when v.vote < 0 and not v.nullified then 1 end),
+       coalesce(sum(case when not v.nullified then v.vote * v.weight end), 0.0)
+from votes v
+group by v.message;
+
+create trigger votes_insert_score
+    after insert
+    on votes
+begin
+    delete from message_scores where message = new.message;
+    insert into message_scores (message, positive_votes, negative_votes, score)
+    select v.message,
+           count(case when v.vote > 0 and not v.nullified then 1 end),
+           count(case when v.vote < 0 and not v.nullified then 1 end),
+           coalesce(sum(case when not v.nullified then v.vote * v.weight end), 0.0)
+    from votes v
+    where v.message = new.message
+    group by v.message;
+end;
+
+create trigger votes_update_score
+    after update
+    on votes
+begin
+    delete from message_scores where message = new.message;
+    insert into message_scores (message, positive_votes, negative_votes, score)
+    select v.message,
+           count(case when v.vote > 0 and not v.nullified then 1 end),
+           count(case when v.vote < 0 and not v.nullified then 1 end),
+           coalesce(sum(case when not v.nullified then v.vote * v.weight end), 0.0)
+    from votes v
+    where v.message = new.message
+    group by v.message;
+end;
+
+-- votes deleted along with their message must not recreate its score
+create trigger votes_delete_score
+    after delete
+    on votes
+begin
+    delete from message_scores where message = old.message;
+    insert into message_scores (message, positive_votes, negative_votes, score)
+    select v.message,
+           count(case when v.vote > 0 and not v.nullified then 1 end),
+           count(case when v.vote < 0 and not v.nullified then 1 end),
+           coalesce(sum(case when not v.nullified then v.vote * v.weight end), 0.0)
+    from votes v
+    where v.message = old.message
+      and exists (select 1 from messages m where m.id = old.message)
+    group by v.message;
+end;
@@ -6,7 +6,142 @@ use serde::Deserialize;
 pub struct Config {
     pub address: String,
     pub packs: PathBuf,
+    #[serde(default = "territories_default")]
+    pub territories: PathBuf,
+    #[serde(default = "worlds_default")]
+    pub worlds: PathBuf,
+    #[serde(default = "glyphs_default")]
+    pub glyphs: PathBuf,
+    /// which worlds share open world messages with each other
+    #[serde(default)]
+    pub scope: Scope,
     pub database: String,
     pub vote_threshold_hide: i32,
     pub max_messages: i32,
+    #[serde(default)]
+    pub expose_shadowban: bool,
+    #[serde(default)]
+    pub voting: VotingConfig,
+    #[serde(default)]
+    pub emotes: EmoteConfig,
+    /// scheduled backups are disabled if this is not set
+    #[serde(default)]
+    pub backups: Option<BackupConfig>,
+    #[serde(default = "cache_seconds_default")]
+    pub cache_seconds: u64,
+    #[serde(default = "max_radius_default")]
+    pub max_radius: f32,
+    /// how close a user's messages can be to each other in the same area
+    #[serde(default = "min_spacing_default")]
+    pub min_spacing: f32,
+}
+
+fn territories_default() -> PathBuf {
+    "territories.yaml".into()
+}
+
+fn worlds_default() -> PathBuf {
+    "worlds.yaml".into()
+}
+
+fn glyphs_default() -> PathBuf {
+    "glyphs.yaml".into()
+}
+
+fn cache_seconds_default() -> u64 {
+    300
+}
+
+fn max_radius_default() -> f32 {
+    250.0
+}
+
+fn min_spacing_default() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    #[default]
+    Global,
+    Region,
+    DataCenter,
+    World,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BackupConfig {
+    pub directory: PathBuf,
+    #[serde(default = "backup_interval_minutes_default")]
+    pub interval_minutes: u64,
+    /// the number of backups to keep, deleting the oldest first
+    #[serde(default)]
+    pub keep: Option<usize>,
+    /// backups older than this are deleted
+    #[serde(default)]
+    pub max_age_days: Option<i64>,
+}
+
+fn backup_interval_minutes_default() -> u64 {
+    24 * 60
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct EmoteConfig {
+    /// the emote ids that can be attached to messages. all emotes are allowed
+    /// if this is not set
+    pub allowed: Option<Vec<u32>>,
+    /// what to do with the author's appearance when a message is written.
+    /// also applied when messages are read, so tightening it covers old ones
+    pub appearance: Appearance,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Appearance {
+    /// store the appearance as sent, unless the author asked for a generic one
+    #[default]
+    Keep,
+    /// keep the author's race, gender and clan but reset their features
+    Generic,
+    /// remove all appearance data, leaving only the emote
+    Strip,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct VotingConfig {
+    /// accounts younger than this are not yet established
+    pub min_account_age_hours: i64,
+    /// accounts need to have written this many messages or have been active
+    /// for `min_active_hours` to be established
+    pub min_messages: i64,
+    pub min_active_hours: i64,
+    /// the weight given to votes from accounts that are not established
+    pub unestablished_weight: f64,
+    /// the number of identical votes two accounts need to have cast on the
+    /// same author's messages to be reported as a possible voting ring
+    pub ring_min_shared_votes: i64,
+    /// how often to re-evaluate the weight of votes from accounts that were
+    /// not established and to look for voting rings
+    pub review_minutes: u64,
+    /// flag every account in a detected voting ring instead of only
+    /// reporting it
+    pub flag_rings: bool,
+}
+
+impl Default for VotingConfig {
+    fn default() -> Self {
+        Self {
+            min_account_age_hours: 72,
+            min_messages: 1,
+            min_active_hours: 2,
+            unestablished_weight: 0.5,
+            ring_min_shared_votes: 5,
+            review_minutes: 60,
+            flag_rings: false,
+        }
+    }
 }
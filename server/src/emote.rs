@@ -0,0 +1,258 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Decode, Sqlite, Type};
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteTypeInfo, SqliteValueRef};
+
+use crate::config::Appearance;
+
+pub const CUSTOMISE_LEN: usize = 26;
+pub const EQUIPMENT_SLOTS: usize = 10;
+pub const MAX_WEAPONS: usize = 3;
+
+// race, gender, body type and clan, which are kept for generic appearances
+const GENERIC_KEPT: &[usize] = &[0, 1, 2, 4];
+// the remaining features, reset to values every race has
+const GENERIC_DEFAULTS: &[(usize, u8)] = &[
+    // height
+    (3, 50),
+    // face
+    (5, 1),
+    // hairstyle
+    (6, 1),
+    // muscle mass or tail length
+    (21, 50),
+    // tail shape
+    (22, 1),
+    // bust size
+    (23, 50),
+];
+
+// the first byte of the binary encoding. emotes stored before the binary
+// encoding are json objects, so they start with `{` instead
+const BINARY_VERSION: u8 = 1;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EmoteData {
+    pub id: u32,
+    pub customise: Vec<u8>,
+    pub equipment: Vec<EquipmentData>,
+    pub weapon: Vec<WeaponData>,
+    pub glasses: u32,
+    pub hat_hidden: bool,
+    pub visor_toggled: bool,
+    pub weapon_hidden: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EquipmentData {
+    pub id: u16,
+    pub variant: u8,
+    pub stain_0: u8,
+    pub stain_1: u8,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WeaponData {
+    pub model_id: WeaponModelId,
+    pub state: u8,
+    pub flags_1: u16,
+    pub flags_2: u8,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WeaponModelId {
+    pub id: u16,
+    pub kind: u16,
+    pub variant: u16,
+    pub stain_0: u8,
+    pub stain_1: u8,
+}
+
+impl EmoteData {
+    pub fn is_valid(&self) -> bool {
+        self.id != 0
+            && self.id <= u16::MAX as u32
+            && self.customise.len() == CUSTOMISE_LEN
+            && self.equipment.len() == EQUIPMENT_SLOTS
+            && self.weapon.len() <= MAX_WEAPONS
+    }
+
+    /// Removes or resets the parts of the emote that identify its author's
+    /// character.
+    pub fn scrub(&mut self, appearance: Appearance) {
+        match appearance {
+            Appearance::Keep => {}
+            // appearances that were already stripped, or stored before emotes
+            // were validated, have nothing that can be kept
+            Appearance::Generic if self.customise.len() != CUSTOMISE_LEN => self.scrub(Appearance::Strip),
+            Appearance::Generic => {
+                let mut customise = vec![0; CUSTOMISE_LEN];
+                for &idx in GENERIC_KEPT {
+                    customise[idx] = self.customise[idx];
+                }
+                for &(idx, value) in GENERIC_DEFAULTS {
+                    customise[idx] = value;
+                }
+                self.customise = customise;
+
+                for equip in &mut self.equipment {
+                    equip.stain_0 = 0;
+                    equip.stain_1 = 0;
+                }
+                for weapon in &mut self.weapon {
+                    weapon.model_id.stain_0 = 0;
+                    weapon.model_id.stain_1 = 0;
+                }
+                self.glasses = 0;
+            }
+            Appearance::Strip => {
+                self.customise.clear();
+                self.equipment.clear();
+                self.weapon.clear();
+                self.glasses = 0;
+            }
+        }
+    }
+
+    /// Encodes a valid emote into the format stored in the database.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(128);
+        bytes.push(BINARY_VERSION);
+        bytes.extend(self.id.to_le_bytes());
+        bytes.push(self.customise.len() as u8);
+        bytes.extend(&self.customise);
+
+        bytes.push(self.equipment.len() as u8);
+        for equip in &self.equipment {
+            bytes.extend(equip.id.to_le_bytes());
+            bytes.extend([equip.variant, equip.stain_0, equip.stain_1]);
+        }
+
+        bytes.push(self.weapon.len() as u8);
+        for weapon in &self.weapon {
+            bytes.extend(weapon.model_id.id.to_le_bytes());
+            bytes.extend(weapon.model_id.kind.to_le_bytes());
+            bytes.extend(weapon.model_id.variant.to_le_bytes());
+            bytes.extend([weapon.model_id.stain_0, weapon.model_id.stain_1, weapon.state]);
+            bytes.extend(weapon.flags_1.to_le_bytes());
+            bytes.push(weapon.flags_2);
+        }
+
+        bytes.extend(self.glasses.to_le_bytes());
+        let flags = self.hat_hidden as u8
+            | (self.visor_toggled as u8) << 1
+            | (self.weapon_hidden as u8) << 2;
+        bytes.push(flags);
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader(bytes);
+        let version = reader.u8()?;
+        if version != BINARY_VERSION {
+            return None;
+        }
+
+        let id = reader.u32()?;
+        let customise_len = reader.u8()? as usize;
+        let customise = reader.take(customise_len)?.to_vec();
+
+        let equipment_len = reader.u8()? as usize;
+        let mut equipment = Vec::with_capacity(equipment_len);
+        for _ in 0..equipment_len {
+            equipment.push(EquipmentData {
+                id: reader.u16()?,
+                variant: reader.u8()?,
+                stain_0: reader.u8()?,
+                stain_1: reader.u8()?,
+            });
+        }
+
+        let weapons = reader.u8()? as usize;
+        let mut weapon = Vec::with_capacity(weapons);
+        for _ in 0..weapons {
+            weapon.push(WeaponData {
+                model_id: WeaponModelId {
+                    id: reader.u16()?,
+                    kind: reader.u16()?,
+                    variant: reader.u16()?,
+                    stain_0: reader.u8()?,
+                    stain_1: reader.u8()?,
+                },
+                state: reader.u8()?,
+                flags_1: reader.u16()?,
+                flags_2: reader.u8()?,
+            });
+        }
+
+        let glasses = reader.u32()?;
+        let flags = reader.u8()?;
+
+        Some(Self {
+            id,
+            customise,
+            equipment,
+            weapon,
+            glasses,
+            hat_hidden: flags & 1 != 0,
+            visor_toggled: flags & (1 << 1) != 0,
+            weapon_hidden: flags & (1 << 2) != 0,
+        })
+    }
+}
+
+/// Applies the configured appearance to an emote read from the database, which
+/// may have been written while the server kept more of it.
+pub fn scrub_stored(emote: &mut Option<EmoteData>, appearance: Appearance) {
+    if let Some(emote) = emote {
+        emote.scrub(appearance);
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+impl Type<Sqlite> for EmoteData {
+    fn type_info() -> SqliteTypeInfo {
+        <Vec<u8> as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <Vec<u8> as Type<Sqlite>>::compatible(ty) || <String as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for EmoteData {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let bytes = <&[u8] as Decode<Sqlite>>::decode(value)?;
+        if bytes.first() == Some(&b'{') {
+            return Ok(serde_json::from_slice(bytes)?);
+        }
+
+        Self::from_bytes(bytes).ok_or_else(|| "invalid emote encoding".into())
+    }
+}
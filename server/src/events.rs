@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::message::RetrievedMessage;
+use crate::State;
+
+// the number of events a slow subscriber can fall behind by before it is told
+// to resync
+pub const EVENT_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventLocation {
+    pub territory: i64,
+    pub world: Option<i64>,
+    pub ward: Option<i64>,
+    pub plot: Option<i64>,
+}
+
+impl EventLocation {
+    pub fn matches(&self, other: &EventLocation, housing: bool) -> bool {
+        if housing {
+            self == other
+        } else {
+            self.territory == other.territory
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TerritoryEvent {
+    pub location: EventLocation,
+    pub author: i64,
+    // messages from shadowbanned users are only sent to their author
+    pub public: bool,
+    // messages scored below the hide threshold are only shown to their author
+    pub hidden: bool,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    MessageAdded {
+        message: Box<RetrievedMessage>,
+    },
+    MessageRemoved {
+        id: String,
+    },
+    VotesChanged {
+        id: String,
+        positive_votes: i32,
+        negative_votes: i32,
+    },
+}
+
+impl State {
+    pub fn publish(&self, event: TerritoryEvent) {
+        // this only fails if nobody is subscribed
+        let _ = self.events.send(Arc::new(event));
+    }
+
+    /// Publishes an event caused by `author`, only showing it to them if they
+    /// are shadowbanned.
+    pub async fn publish_from(&self, author: i64, location: EventLocation, kind: EventKind) -> Result<()> {
+        if self.events.receiver_count() == 0 {
+            return Ok(());
+        }
+
+        let shadowbanned = sqlx::query_scalar!(
+            // language=sqlite
+            r#"select shadowbanned as "shadowbanned: bool" from users where id = ?"#,
+            author,
+        )
+            .fetch_one(&self.db)
+            .await
+            .context("could not get user from database")?;
+
+        let hidden = match &kind {
+            EventKind::MessageAdded { message } => message.score < self.config.vote_threshold_hide.into(),
+            _ => false,
+        };
+
+        self.publish(TerritoryEvent {
+            location,
+            author,
+            public: !shadowbanned,
+            hidden,
+            kind,
+        });
+
+        Ok(())
+    }
+
+    pub async fn publish_votes(&self, message_id: &str) -> Result<()> {
+        if self.events.receiver_count() == 0 {
+            return Ok(());
+        }
+
+        let message = sqlx::query!(
+            // language=sqlite
+            r#"
+                select m.territory,
+                       m.world,
+                       m.ward,
+                       m.plot,
+                       m.user,
+                       u.shadowbanned as "shadowbanned: bool",
+                       coalesce(s.positive_votes, 0) as "positive_votes!: i32",
+                       coalesce(s.negative_votes, 0) as "negative_votes!: i32",
+                       coalesce(s.score, 0.0) as "score!: f64"
+                from messages m
+                         left join message_scores s on m.id = s.message
+                         inner join users u on m.user = u.id
+                where m.id = ?"#,
+            message_id,
+        )
+            .fetch_optional(&self.db)
+            .await
+            .context("could not get message votes from database")?;
+
+        if let Some(message) = message {
+            self.publish(TerritoryEvent {
+                location: EventLocation {
+                    territory: message.territory,
+                    world: message.world,
+                    ward: message.ward,
+                    plot: message.plot,
+                },
+                author: message.user,
+                public: !message.shadowbanned,
+                hidden: message.score < self.config.vote_threshold_hide.into(),
+                kind: EventKind::VotesChanged {
+                    id: message_id.to_string(),
+                    positive_votes: message.positive_votes,
+                    negative_votes: message.negative_votes,
+                },
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Glyph {
+    pub id: i8,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entitlement: Option<String>,
+}
+
+impl State {
+    pub async fn update_glyphs(&self) -> Result<()> {
+        let text = tokio::fs::read_to_string(&self.config.glyphs)
+            .await
+            .with_context(|| format!("could not read glyphs at {:?}", self.config.glyphs))?;
+        let glyphs: Vec<Glyph> = serde_yaml::from_str(&text)
+            .context("could not parse glyphs")?;
+
+        println!("added {} glyphs", glyphs.len());
+        *self.glyphs.write().await = glyphs;
+
+        Ok(())
+    }
+
+    pub async fn glyph(&self, id: i8) -> Option<Glyph> {
+        self.glyphs.read()
+            .await
+            .iter()
+            .find(|glyph| glyph.id == id)
+            .cloned()
+    }
+}
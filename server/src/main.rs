@@ -1,9 +1,8 @@
-#![feature(extract_if)]
-
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use sqlx::{Executor, Pool, Sqlite};
@@ -11,18 +10,36 @@ use sqlx::migrate::Migrator;
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 use tokio::net::{TcpListener, UnixListener};
 use tokio::runtime::Handle;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use tokio_stream::wrappers::{TcpListenerStream, UnixListenerStream};
 use uuid::Uuid;
 
+use crate::cache::TtlCache;
 use crate::config::Config;
+use crate::events::TerritoryEvent;
+use crate::glyph::Glyph;
+use crate::message::ExportedMessage;
 use crate::pack::Pack;
+use crate::territory::Territory;
+use crate::world::DataCenter;
+use crate::web::{GridBucket, GridKey, TerritoryStats, TopKey};
 
 mod pack;
 mod message;
 mod web;
 mod util;
 mod config;
+mod voting;
+mod events;
+mod cache;
+mod emote;
+mod query;
+mod territory;
+mod world;
+mod glyph;
+mod maintenance;
+#[cfg(test)]
+mod tests;
 
 static MIGRATOR: Migrator = sqlx::migrate!();
 
@@ -30,9 +47,33 @@ pub struct State {
     pub config: Config,
     pub db: Pool<Sqlite>,
     pub packs: RwLock<HashMap<Uuid, Pack>>,
+    pub territories: RwLock<HashMap<u32, Territory>>,
+    pub worlds: RwLock<Vec<DataCenter>>,
+    pub glyphs: RwLock<Vec<Glyph>>,
+    pub events: broadcast::Sender<Arc<TerritoryEvent>>,
+    pub top_cache: TtlCache<TopKey, Vec<ExportedMessage>>,
+    pub stats_cache: TtlCache<Option<Vec<u32>>, Vec<TerritoryStats>>,
+    pub grid_cache: TtlCache<GridKey, Vec<GridBucket>>,
 }
 
 impl State {
+    pub fn new(config: Config, db: Pool<Sqlite>) -> Self {
+        let (events, _) = broadcast::channel(events::EVENT_CAPACITY);
+        let cache_ttl = Duration::from_secs(config.cache_seconds);
+        Self {
+            config,
+            db,
+            packs: Default::default(),
+            territories: Default::default(),
+            worlds: Default::default(),
+            glyphs: Default::default(),
+            events,
+            top_cache: TtlCache::new(cache_ttl, cache::MAX_ENTRIES),
+            stats_cache: TtlCache::new(cache_ttl, cache::MAX_ENTRIES),
+            grid_cache: TtlCache::new(cache_ttl, cache::MAX_ENTRIES),
+        }
+    }
+
     pub async fn update_packs(&self) -> Result<()> {
         let mut packs = HashMap::new();
 
@@ -69,17 +110,40 @@ impl State {
     }
 }
 
+const USAGE: &str = "usage: server [command] <config> [arguments]
+
+commands:
+  serve                  run the server (the default)
+  migrate                run database migrations
+  vacuum                 rebuild the database file to reclaim space
+  backup <path>          copy the database to path while it is in use
+  prune [inactive days]  remove leftover votes and expired link codes, and
+                         users with no messages, codes or entitlements
+                         inactive for that many days
+  check                  check database integrity and foreign keys
+
+e.g. server backup config.toml backup.sqlite";
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args: Vec<String> = std::env::args().skip(1).collect();
-    if args.is_empty() {
-        eprintln!("usage: server [config]");
+    let (command, args) = match args.first().map(String::as_str) {
+        Some(command @ ("serve" | "migrate" | "vacuum" | "backup" | "prune" | "check")) => (command, &args[1..]),
+        Some(_) => ("serve", &args[..]),
+        None => {
+            eprintln!("{USAGE}");
+            return Ok(());
+        }
+    };
+
+    let Some(config_path) = args.first() else {
+        eprintln!("{USAGE}");
         return Ok(());
-    }
+    };
 
-    let config_str = tokio::fs::read_to_string(&args[0])
+    let config_str = tokio::fs::read_to_string(config_path)
         .await
-        .with_context(|| format!("could not read config file at {}", args[0]))?;
+        .with_context(|| format!("could not read config file at {config_path}"))?;
     let config: Config = toml::from_str(&config_str)
         .context("could not parse config file")?;
 
@@ -97,20 +161,47 @@ async fn main() -> Result<()> {
         .connect_with(options.filename(&config.database))
         .await
         .context("could not connect to database")?;
+
+    match command {
+        "migrate" => return maintenance::migrate(&pool).await,
+        "vacuum" => return maintenance::vacuum(&pool).await,
+        "backup" => {
+            let path = args.get(1).context("a path to back up to is required")?;
+            return maintenance::backup(&config.database, path).await;
+        }
+        "check" => return maintenance::check(&pool).await,
+        _ => {}
+    }
+
     MIGRATOR.run(&pool)
         .await
         .context("could not run database migrations")?;
 
-    let state = Arc::new(State {
-        config,
-        db: pool,
-        packs: Default::default(),
-    });
+    if command == "prune" {
+        let inactive_days = args.get(1)
+            .map(|days| days.parse())
+            .transpose()
+            .context("inactive days must be a number")?;
+        return maintenance::prune(&pool, inactive_days).await;
+    }
+
+    let state = Arc::new(State::new(config, pool));
 
     println!("adding packs");
     state.update_packs().await?;
 
+    println!("adding territories");
+    state.update_territories().await?;
+
+    println!("adding worlds");
+    state.update_worlds().await?;
+
+    println!("adding glyphs");
+    state.update_glyphs().await?;
+
     spawn_command_reader(Arc::clone(&state), Handle::current());
+    maintenance::spawn_backups(Arc::clone(&state));
+    voting::spawn_vote_review(Arc::clone(&state));
 
     let address = state.config.address.clone();
     let server = warp::serve(web::routes(state));
@@ -143,9 +234,63 @@ fn spawn_command_reader(state: Arc<State>, handle: Handle) {
                         eprintln!("failed to update packs: {e:#?}");
                     }
                 });
+            } else if read == "reload territories" {
+                let state = Arc::clone(&state);
+                handle.spawn(async move {
+                    if let Err(e) = state.update_territories().await {
+                        eprintln!("failed to update territories: {e:#?}");
+                    }
+                });
+            } else if read == "reload worlds" {
+                let state = Arc::clone(&state);
+                handle.spawn(async move {
+                    if let Err(e) = state.update_worlds().await {
+                        eprintln!("failed to update worlds: {e:#?}");
+                    }
+                });
+            } else if read == "reload glyphs" {
+                let state = Arc::clone(&state);
+                handle.spawn(async move {
+                    if let Err(e) = state.update_glyphs().await {
+                        eprintln!("failed to update glyphs: {e:#?}");
+                    }
+                });
+            } else if read == "detect rings" {
+                let state = Arc::clone(&state);
+                handle.spawn(async move {
+                    match state.detect_voting_rings().await {
+                        Ok(rings) if rings.is_empty() => println!("no voting rings detected"),
+                        Ok(rings) => for ring in rings {
+                            println!("{ring}");
+                        },
+                        Err(e) => eprintln!("failed to detect voting rings: {e:#?}"),
+                    }
+                });
+            } else if let Some(id) = read.strip_prefix("flag ") {
+                spawn_set_vote_flagged(Arc::clone(&state), &handle, id, true);
+            } else if let Some(id) = read.strip_prefix("unflag ") {
+                spawn_set_vote_flagged(Arc::clone(&state), &handle, id, false);
             }
 
             line.clear();
         }
     });
 }
+
+fn spawn_set_vote_flagged(state: Arc<State>, handle: &Handle, id: &str, flagged: bool) {
+    let id = match id.trim().parse::<i64>() {
+        Ok(id) => id,
+        Err(_) => {
+            eprintln!("invalid user id: {id}");
+            return;
+        }
+    };
+
+    handle.spawn(async move {
+        match state.set_vote_flagged(id, flagged).await {
+            Ok(()) if flagged => println!("flagged user {id}, their votes are now nullified"),
+            Ok(()) => println!("unflagged user {id}, their votes are restored"),
+            Err(e) => eprintln!("failed to update user {id}: {e:#?}"),
+        }
+    });
+}
@@ -0,0 +1,337 @@
+use std::cmp::Reverse;
+use std::ffi::{c_int, CStr, CString};
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDateTime, Utc};
+use libsqlite3_sys as ffi;
+use sqlx::{Pool, Row, Sqlite};
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::{MIGRATOR, State};
+use crate::config::BackupConfig;
+
+pub async fn migrate(db: &Pool<Sqlite>) -> Result<()> {
+    MIGRATOR.run(db)
+        .await
+        .context("could not run database migrations")?;
+    println!("database is up to date");
+    Ok(())
+}
+
+pub async fn vacuum(db: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query(
+        // language=sqlite
+        "vacuum",
+    )
+        .execute(db)
+        .await
+        .context("could not vacuum database")?;
+    println!("vacuumed database");
+    Ok(())
+}
+
+// how much of the database is copied before letting writers back in
+const BACKUP_PAGES_PER_STEP: c_int = 256;
+const BACKUP_STEP_PAUSE: Duration = Duration::from_millis(20);
+// a write between steps makes the copy start over, so give up rather than
+// copying forever on a busy server
+const BACKUP_MAX_RESTARTS: u32 = 10;
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Writes a consistent copy of the database to `path` while it is in use,
+/// using SQLite's online backup API on connections of its own. The copy is
+/// only moved to `path`, which must not already exist, once it is complete.
+pub async fn backup(database: &str, path: &str) -> Result<()> {
+    let exists = tokio::fs::try_exists(path)
+        .await
+        .with_context(|| format!("could not check for an existing file at {path}"))?;
+    if exists {
+        anyhow::bail!("a file already exists at {path}");
+    }
+
+    let partial = format!("{path}{PARTIAL_SUFFIX}");
+    let (source, destination) = (database.to_owned(), partial.clone());
+    let copied = tokio::task::spawn_blocking(move || online_backup(&source, &destination))
+        .await
+        .context("backup task panicked")
+        .and_then(|copied| copied);
+    if let Err(e) = copied {
+        // the copy might have failed before creating the file
+        match tokio::fs::remove_file(&partial).await {
+            Err(remove) if remove.kind() != std::io::ErrorKind::NotFound => eprintln!("could not remove partial backup {partial}: {remove:#?}"),
+            _ => {}
+        }
+        return Err(e).with_context(|| format!("could not back up database to {path}"));
+    }
+
+    tokio::fs::rename(&partial, path)
+        .await
+        .with_context(|| format!("could not move backup from {partial} to {path}"))?;
+    println!("backed up database to {path}");
+    Ok(())
+}
+
+fn online_backup(database: &str, path: &str) -> Result<()> {
+    let source = RawConnection::open(database, ffi::SQLITE_OPEN_READONLY)?;
+    let destination = RawConnection::open(path, ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE)?;
+
+    // SAFETY: both connections are open until the end of this function, and
+    // the backup is finished before they are closed
+    unsafe {
+        let backup = ffi::sqlite3_backup_init(destination.0, c"main".as_ptr(), source.0, c"main".as_ptr());
+        if backup.is_null() {
+            anyhow::bail!("could not start backup: {}", destination.error());
+        }
+
+        // copy a few pages at a time, pausing in between so that the server
+        // can write. the copy starts over if it does, which shows up as a step
+        // that copies pages without getting any closer to the end
+        let mut remaining = None;
+        let mut restarts = 0;
+        loop {
+            match ffi::sqlite3_backup_step(backup, BACKUP_PAGES_PER_STEP) {
+                ffi::SQLITE_OK => {
+                    let now_remaining = ffi::sqlite3_backup_remaining(backup);
+                    if remaining.is_some_and(|remaining| now_remaining >= remaining) {
+                        restarts += 1;
+                        if restarts > BACKUP_MAX_RESTARTS {
+                            ffi::sqlite3_backup_finish(backup);
+                            anyhow::bail!("the database changed too often to finish copying it");
+                        }
+                    }
+                    remaining = Some(now_remaining);
+                }
+                ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED => {}
+                _ => break,
+            }
+
+            std::thread::sleep(BACKUP_STEP_PAUSE);
+        }
+
+        // reports the error from the step, if there was one
+        if ffi::sqlite3_backup_finish(backup) != ffi::SQLITE_OK {
+            anyhow::bail!("could not copy database: {}", destination.error());
+        }
+    }
+
+    Ok(())
+}
+
+/// A connection opened outside of sqlx, for the parts of SQLite it doesn't
+/// expose.
+struct RawConnection(*mut ffi::sqlite3);
+
+impl RawConnection {
+    fn open(path: &str, flags: c_int) -> Result<Self> {
+        let c_path = CString::new(path).context("database path contains a nul byte")?;
+        let mut db = ptr::null_mut();
+        // SAFETY: c_path outlives the call, and a handle is returned even when
+        // opening fails so that it can be closed
+        let rc = unsafe { ffi::sqlite3_open_v2(c_path.as_ptr(), &mut db, flags, ptr::null()) };
+        let connection = Self(db);
+        if rc != ffi::SQLITE_OK {
+            anyhow::bail!("could not open {path}: {}", connection.error());
+        }
+
+        Ok(connection)
+    }
+
+    fn error(&self) -> String {
+        // SAFETY: sqlite3_errmsg always returns a valid string, even for a null
+        // handle
+        unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.0)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl Drop for RawConnection {
+    fn drop(&mut self) {
+        // SAFETY: closing a null handle is a no-op
+        unsafe {
+            ffi::sqlite3_close(self.0);
+        }
+    }
+}
+
+/// Removes rows left behind by deleted messages and users, and optionally
+/// users who have not been seen in `inactive_days` and have nothing to lose:
+/// no messages, claimed codes or entitlements.
+pub async fn prune(db: &Pool<Sqlite>, inactive_days: Option<u32>) -> Result<()> {
+    let mut t = db.begin()
+        .await
+        .context("could not start transaction")?;
+
+    let votes = sqlx::query!(
+        // language=sqlite
+        r#"
+            delete from votes
+            where message not in (select id from messages)
+               or cast(user as integer) not in (select id from users)"#,
+    )
+        .execute(&mut *t)
+        .await
+        .context("could not prune votes")?
+        .rows_affected();
+
+    let links = sqlx::query!(
+        // language=sqlite
+        "delete from link_codes where expires <= current_timestamp",
+    )
+        .execute(&mut *t)
+        .await
+        .context("could not prune link codes")?
+        .rows_affected();
+
+    let users = match inactive_days {
+        Some(days) => {
+            let modifier = format!("-{days} days");
+            sqlx::query!(
+                // language=sqlite
+                r#"
+                    delete from users
+                    where last_seen < datetime(current_timestamp, ?)
+                      and extra = 0
+                      and id not in (select user from messages)
+                      and id not in (select user from used_codes)
+                      and id not in (select user from user_entitlements)"#,
+                modifier,
+            )
+                .execute(&mut *t)
+                .await
+                .context("could not prune inactive users")?
+                .rows_affected()
+        }
+        None => 0,
+    };
+
+    t.commit()
+        .await
+        .context("could not commit transaction")?;
+
+    println!("pruned {votes} votes, {links} link codes and {users} inactive users");
+    Ok(())
+}
+
+pub async fn check(db: &Pool<Sqlite>) -> Result<()> {
+    let integrity: Vec<String> = sqlx::query(
+        // language=sqlite
+        "pragma integrity_check",
+    )
+        .fetch_all(db)
+        .await
+        .context("could not check database integrity")?
+        .into_iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    let integrity_ok = integrity == ["ok"];
+    if !integrity_ok {
+        for problem in &integrity {
+            eprintln!("integrity: {problem}");
+        }
+    }
+
+    let foreign_keys = sqlx::query(
+        // language=sqlite
+        "pragma foreign_key_check",
+    )
+        .fetch_all(db)
+        .await
+        .context("could not check foreign keys")?;
+
+    for row in &foreign_keys {
+        let table: String = row.get("table");
+        let rowid: Option<i64> = row.get("rowid");
+        let parent: String = row.get("parent");
+        eprintln!("foreign key: row {rowid:?} in {table} references a missing row in {parent}");
+    }
+
+    if !integrity_ok || !foreign_keys.is_empty() {
+        anyhow::bail!("database check failed");
+    }
+
+    println!("database is ok");
+    Ok(())
+}
+
+const BACKUP_PREFIX: &str = "backup-";
+const BACKUP_SUFFIX: &str = ".sqlite";
+const BACKUP_TIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// Takes a backup on the configured schedule until the server stops.
+pub fn spawn_backups(state: Arc<State>) {
+    let Some(config) = &state.config.backups else {
+        return;
+    };
+
+    let period = Duration::from_secs(config.interval_minutes.max(1) * 60);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = scheduled_backup(&state).await {
+                eprintln!("scheduled backup failed: {e:#?}");
+            }
+        }
+    });
+}
+
+async fn scheduled_backup(state: &State) -> Result<()> {
+    let Some(config) = &state.config.backups else {
+        return Ok(());
+    };
+
+    tokio::fs::create_dir_all(&config.directory)
+        .await
+        .with_context(|| format!("could not create backup directory at {:?}", config.directory))?;
+
+    let now = Utc::now().naive_utc();
+    let name = format!("{BACKUP_PREFIX}{}{BACKUP_SUFFIX}", now.format(BACKUP_TIME_FORMAT));
+    let path = config.directory.join(name);
+    backup(&state.config.database, &path.to_string_lossy()).await?;
+
+    rotate_backups(config, now).await
+}
+
+async fn rotate_backups(config: &BackupConfig, now: NaiveDateTime) -> Result<()> {
+    let mut backups = Vec::new();
+    let mut dir = tokio::fs::read_dir(&config.directory)
+        .await
+        .context("could not read backup directory")?;
+    while let Some(entry) = dir.next_entry().await.context("could not read backup directory")? {
+        let name = entry.file_name();
+        let taken = name.to_str()
+            .and_then(|name| name.strip_prefix(BACKUP_PREFIX))
+            .and_then(|name| name.strip_suffix(BACKUP_SUFFIX))
+            .and_then(|time| NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).ok());
+
+        // leave anything that wasn't made by us alone
+        if let Some(taken) = taken {
+            backups.push((taken, entry.path()));
+        }
+    }
+
+    // newest first
+    backups.sort_unstable_by_key(|(taken, _)| Reverse(*taken));
+
+    for (i, (taken, path)) in backups.into_iter().enumerate() {
+        let too_many = config.keep.is_some_and(|keep| i >= keep.max(1));
+        let too_old = config.max_age_days.is_some_and(|days| now - taken > chrono::Duration::days(days));
+        if !too_many && !too_old {
+            continue;
+        }
+
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => println!("removed old backup {path:?}"),
+            Err(e) => eprintln!("could not remove old backup {path:?}: {e:#?}"),
+        }
+    }
+
+    Ok(())
+}
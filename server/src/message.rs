@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
-use sqlx::types::{chrono::NaiveDateTime, Json};
+use sqlx::FromRow;
+use sqlx::types::chrono::NaiveDateTime;
 use uuid::Uuid;
 
+use crate::emote::EmoteData;
+
 #[derive(Debug, Deserialize)]
 pub struct Message {
     pub territory: u32,
@@ -32,13 +35,16 @@ pub struct Message {
 
     #[serde(default)]
     pub emote: Option<EmoteData>,
+    /// hides the author's appearance from the emote
+    #[serde(default)]
+    pub generic_appearance: bool,
 }
 
 fn glyph_default() -> i8 {
     3
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, FromRow)]
 pub struct RetrievedMessage {
     pub id: String,
     pub x: f64,
@@ -50,16 +56,20 @@ pub struct RetrievedMessage {
     pub negative_votes: i32,
     pub user_vote: i64,
     pub glyph: i64,
-    pub emote: Option<Json<Option<EmoteData>>>,
+    pub emote: Option<EmoteData>,
+    #[serde(skip)]
+    pub score: f64,
     #[serde(skip)]
     pub created: NaiveDateTime,
     #[serde(skip)]
     pub user: i64,
     #[serde(skip)]
     pub last_seen_minutes: i64,
+    #[serde(skip)]
+    pub votes_updated: Option<NaiveDateTime>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, FromRow)]
 pub struct RetrievedMessageTerritory {
     pub id: String,
     pub territory: i64,
@@ -75,12 +85,10 @@ pub struct RetrievedMessageTerritory {
     pub negative_votes: i32,
     pub user_vote: i64,
     pub glyph: i64,
-    pub emote: Option<Json<Option<EmoteData>>>,
-    #[serde(skip)]
-    pub created: NaiveDateTime,
+    pub emote: Option<EmoteData>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, FromRow)]
 pub struct OwnMessage {
     pub id: String,
     pub territory: i64,
@@ -96,45 +104,31 @@ pub struct OwnMessage {
     pub negative_votes: i32,
     pub user_vote: i64,
     pub glyph: i64,
-    pub emote: Option<Json<Option<EmoteData>>>,
+    pub emote: Option<EmoteData>,
+    #[serde(skip)]
+    pub score: f64,
     #[serde(skip)]
     pub created: NaiveDateTime,
     pub is_hidden: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct EmoteData {
-    pub id: u32,
-    pub customise: Vec<u8>,
-    pub equipment: Vec<EquipmentData>,
-    pub weapon: Vec<WeaponData>,
-    pub glasses: u32,
-    pub hat_hidden: bool,
-    pub visor_toggled: bool,
-    pub weapon_hidden: bool,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct EquipmentData {
-    pub id: u16,
-    pub variant: u8,
-    pub stain_0: u8,
-    pub stain_1: u8,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct WeaponData {
-    pub model_id: WeaponModelId,
-    pub state: u8,
-    pub flags_1: u16,
-    pub flags_2: u8,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct WeaponModelId {
-    pub id: u16,
-    pub kind: u16,
-    pub variant: u16,
-    pub stain_0: u8,
-    pub stain_1: u8,
+/// A message with its location and tallies, as exported by its author or listed
+/// in the top messages.
+#[derive(Debug, Serialize)]
+pub struct ExportedMessage {
+    pub id: String,
+    pub territory: i64,
+    pub world: Option<i64>,
+    pub ward: Option<i64>,
+    pub plot: Option<i64>,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f64,
+    pub message: String,
+    pub positive_votes: i32,
+    pub negative_votes: i32,
+    pub glyph: i64,
+    pub emote: Option<EmoteData>,
+    pub created: NaiveDateTime,
 }
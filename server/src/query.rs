@@ -0,0 +1,279 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite};
+use sqlx::sqlite::SqliteRow;
+
+// the superset of columns used by RetrievedMessage, RetrievedMessageTerritory,
+// OwnMessage and ExportedMessage. each struct only reads the columns it has
+// fields for. tallies come from message_scores, which has no row for messages
+// without votes
+const SELECT: &str = r#"
+    select m.id,
+           m.territory,
+           m.world,
+           m.ward,
+           m.plot,
+           m.x,
+           m.y,
+           m.z,
+           m.yaw,
+           m.message,
+           coalesce(s.positive_votes, 0) as positive_votes,
+           coalesce(s.negative_votes, 0) as negative_votes,
+           coalesce(s.score, 0.0) as score,
+           coalesce((select v.vote from votes v where v.message = m.id and v.user = "#;
+
+const SELECT_REST: &str = r#"), 0) as user_vote,
+           m.glyph,
+           m.emote,
+           m.created,
+           m.user,
+           coalesce(cast((julianday(current_timestamp) - julianday(u.last_seen)) * 1440 as int), 0) as last_seen_minutes,
+           m.votes_updated,
+           0 as is_hidden
+    from messages m"#;
+
+// the score is filtered and sorted on by its expression, since the bare name
+// would refer to s.score, which is null for messages without votes
+const JOINS: &str = r#"
+             left join message_scores s on m.id = s.message
+             inner join users u on m.user = u.id
+    where 1"#;
+
+/// The order messages are returned in. Each order ends on the message id so
+/// that pages can carry on from a [`MessageCursor`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageOrder {
+    /// newest first
+    #[default]
+    Created,
+    /// highest score first, then newest
+    Score,
+    /// by territory, then newest
+    Territory,
+}
+
+/// The sort keys of the last message on a page, and the order they are for.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MessageCursor {
+    pub order: MessageOrder,
+    pub territory: i64,
+    pub score: f64,
+    pub created: NaiveDateTime,
+    pub id: String,
+}
+
+/// Builds the query used to retrieve messages along with their vote tallies,
+/// shared by every route that returns messages.
+pub struct MessageQuery {
+    viewer: i64,
+    id: Option<String>,
+    author: Option<i64>,
+    territory: Option<i64>,
+    housing: Option<(Option<u32>, Option<u32>, Option<u32>)>,
+    worlds: Option<Vec<u32>>,
+    bounds: Option<(f64, f64, f64, f64)>,
+    visible: bool,
+    hidden: Option<(f64, bool)>,
+    order: Option<MessageOrder>,
+    after: Option<MessageCursor>,
+    limit: Option<u32>,
+}
+
+impl MessageQuery {
+    pub fn new(viewer: i64) -> Self {
+        Self {
+            viewer,
+            id: None,
+            author: None,
+            territory: None,
+            housing: None,
+            worlds: None,
+            bounds: None,
+            visible: false,
+            hidden: None,
+            order: None,
+            after: None,
+            limit: None,
+        }
+    }
+
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn author(mut self, author: i64) -> Self {
+        self.author = Some(author);
+        self
+    }
+
+    pub fn territory(mut self, territory: i64) -> Self {
+        self.territory = Some(territory);
+        self
+    }
+
+    pub fn housing(mut self, world: Option<u32>, ward: Option<u32>, plot: Option<u32>) -> Self {
+        self.housing = Some((world, ward, plot));
+        self
+    }
+
+    pub fn worlds(mut self, worlds: Vec<u32>) -> Self {
+        self.worlds = Some(worlds);
+        self
+    }
+
+    pub fn bounds(mut self, min_x: f64, max_x: f64, min_z: f64, max_z: f64) -> Self {
+        self.bounds = Some((min_x, max_x, min_z, max_z));
+        self
+    }
+
+    /// Hides messages from shadowbanned users from everyone but their author.
+    pub fn visible(mut self) -> Self {
+        self.visible = true;
+        self
+    }
+
+    /// Only returns messages that are (or aren't) hidden by a score below
+    /// `threshold`.
+    pub fn hidden(mut self, threshold: f64, hidden: bool) -> Self {
+        self.hidden = Some((threshold, hidden));
+        self
+    }
+
+    pub fn order(mut self, order: MessageOrder) -> Self {
+        self.order = Some(order);
+        self
+    }
+
+    /// Only returns messages that come after `cursor` in the query's order.
+    pub fn after(mut self, cursor: MessageCursor) -> Self {
+        self.after = Some(cursor);
+        self
+    }
+
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn build(self) -> QueryBuilder<'static, Sqlite> {
+        let mut builder = QueryBuilder::new(SELECT);
+        builder.push_bind(self.viewer);
+        builder.push(SELECT_REST);
+
+        // a bounding box lets the position index narrow the search
+        if self.territory.is_some() && self.bounds.is_some() {
+            builder.push(" indexed by messages_territory_x_z_idx");
+        }
+
+        builder.push(JOINS);
+
+        if let Some(id) = self.id {
+            builder.push(" and m.id = ").push_bind(id);
+        }
+
+        if let Some(author) = self.author {
+            builder.push(" and m.user = ").push_bind(author);
+        }
+
+        if let Some(territory) = self.territory {
+            builder.push(" and m.territory = ").push_bind(territory);
+        }
+
+        if let Some((world, ward, plot)) = self.housing {
+            builder.push(" and m.world is ").push_bind(world);
+            builder.push(" and m.ward is ").push_bind(ward);
+            builder.push(" and m.plot is ").push_bind(plot);
+        }
+
+        if let Some(worlds) = self.worlds {
+            builder.push(" and m.world in (");
+            let mut separated = builder.separated(", ");
+            for world in worlds {
+                separated.push_bind(world);
+            }
+            separated.push_unseparated(")");
+        }
+
+        if let Some((min_x, max_x, min_z, max_z)) = self.bounds {
+            builder.push(" and m.x between ").push_bind(min_x).push(" and ").push_bind(max_x);
+            builder.push(" and m.z between ").push_bind(min_z).push(" and ").push_bind(max_z);
+        }
+
+        if self.visible {
+            builder.push(" and (m.user = ").push_bind(self.viewer).push(" or not u.shadowbanned)");
+        }
+
+        builder.push(" group by m.id having 1");
+
+        if let Some((threshold, hidden)) = self.hidden {
+            builder.push(if hidden { " and coalesce(s.score, 0.0) < " } else { " and coalesce(s.score, 0.0) >= " })
+                .push_bind(threshold);
+        }
+
+        let ordered = self.order.is_some() || self.after.is_some();
+        let order = self.order.unwrap_or_default();
+        if let Some(cursor) = self.after {
+            // (key, created, id) comes after the cursor, with created sorted
+            // newest first and id as the tiebreaker
+            builder.push(" and (");
+            match order {
+                MessageOrder::Created => {}
+                MessageOrder::Score => {
+                    builder.push("coalesce(s.score, 0.0) < ").push_bind(cursor.score);
+                    builder.push(" or coalesce(s.score, 0.0) = ").push_bind(cursor.score).push(" and (");
+                }
+                MessageOrder::Territory => {
+                    builder.push("m.territory > ").push_bind(cursor.territory);
+                    builder.push(" or m.territory = ").push_bind(cursor.territory).push(" and (");
+                }
+            }
+
+            builder.push("m.created < datetime(").push_bind(cursor.created).push(")");
+            builder.push(" or m.created = datetime(").push_bind(cursor.created).push(")");
+            builder.push(" and m.id > ").push_bind(cursor.id);
+
+            if !matches!(order, MessageOrder::Created) {
+                builder.push(")");
+            }
+            builder.push(")");
+        }
+
+        if ordered {
+            builder.push(match order {
+                MessageOrder::Created => " order by m.created desc, m.id",
+                MessageOrder::Score => " order by coalesce(s.score, 0.0) desc, m.created desc, m.id",
+                MessageOrder::Territory => " order by m.territory, m.created desc, m.id",
+            });
+        }
+
+        if let Some(limit) = self.limit {
+            builder.push(" limit ").push_bind(limit);
+        }
+
+        builder
+    }
+
+    pub async fn fetch_all<T>(self, db: &Pool<Sqlite>) -> Result<Vec<T>>
+        where T: for<'r> FromRow<'r, SqliteRow> + Send + Unpin,
+    {
+        self.build()
+            .build_query_as()
+            .fetch_all(db)
+            .await
+            .context("could not get messages from database")
+    }
+
+    pub async fn fetch_optional<T>(self, db: &Pool<Sqlite>) -> Result<Option<T>>
+        where T: for<'r> FromRow<'r, SqliteRow> + Send + Unpin,
+    {
+        self.build()
+            .build_query_as()
+            .fetch_optional(db)
+            .await
+            .context("could not get message from database")
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::State;
+use crate::web::WebError;
+
+const MAX_WARD: u32 = 30;
+const MAX_PLOT: u32 = 60;
+const MAX_ROOM: u32 = 90;
+
+// apartments are stored in the plot column as 10000 for the main building
+// and 15000 for the subdivision, plus the room number for rooms
+const APARTMENT: u32 = 10_000;
+const APARTMENT_WING: u32 = 5_000;
+
+// no map extends this far from its origin, so this bounds territories that
+// don't have bounds of their own
+const MAX_COORDINATE: f32 = 4_096.0;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Territory {
+    pub id: u32,
+    pub name: String,
+    #[serde(default)]
+    pub expansion: Option<String>,
+    #[serde(default)]
+    pub housing: Option<HousingKind>,
+    #[serde(default = "writable_default")]
+    pub writable: bool,
+    /// the area messages can be written in, if known
+    #[serde(default)]
+    pub bounds: Option<Bounds>,
+}
+
+fn writable_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Bounds {
+    pub min_x: f32,
+    pub max_x: f32,
+    pub min_y: f32,
+    pub max_y: f32,
+    pub min_z: f32,
+    pub max_z: f32,
+}
+
+impl Bounds {
+    pub fn contains(&self, x: f32, y: f32, z: f32) -> bool {
+        (self.min_x..=self.max_x).contains(&x)
+            && (self.min_y..=self.max_y).contains(&y)
+            && (self.min_z..=self.max_z).contains(&z)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HousingKind {
+    /// The outdoor area of a ward. Messages are scoped to the ward.
+    District,
+    /// The inside of a house, private chambers or a company workshop.
+    Interior,
+    /// An apartment building's lobby.
+    Lobby,
+    /// A room in an apartment building.
+    Apartment,
+}
+
+impl Territory {
+    /// Territories missing from the data file are open world and writable.
+    fn unknown(id: u32) -> Self {
+        Self {
+            id,
+            name: String::new(),
+            expansion: None,
+            housing: None,
+            writable: true,
+            bounds: None,
+        }
+    }
+
+    pub fn is_housing(&self) -> bool {
+        self.housing.is_some()
+    }
+
+    /// Checks that a position is finite and inside this territory's bounds.
+    pub fn validate_position(&self, x: f32, y: f32, z: f32, yaw: f32) -> Result<(), WebError> {
+        if ![x, y, z, yaw].iter().all(|n| n.is_finite()) {
+            return Err(WebError::InvalidPosition);
+        }
+
+        if ![x, y, z].iter().all(|n| n.abs() <= MAX_COORDINATE) {
+            return Err(WebError::InvalidPosition);
+        }
+
+        match self.bounds {
+            Some(bounds) if !bounds.contains(x, y, z) => Err(WebError::InvalidPosition),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the world, ward and plot describe a real location in this
+    /// territory.
+    pub fn validate_location(&self, world: Option<u32>, ward: Option<u32>, plot: Option<u32>) -> Result<(), WebError> {
+        let kind = match self.housing {
+            Some(kind) => kind,
+            None if ward.is_some() || plot.is_some() => return Err(WebError::UnnecessaryHousingInfo),
+            None => return Ok(()),
+        };
+
+        let ward = match (world, ward) {
+            (Some(_), Some(ward)) => ward,
+            _ => return Err(WebError::MissingHousingInfo),
+        };
+
+        if !(1..=MAX_WARD).contains(&ward) {
+            return Err(WebError::InvalidHousingInfo("ward"));
+        }
+
+        let valid = match (kind, plot) {
+            (HousingKind::District, None) => true,
+            (HousingKind::District, Some(_)) => return Err(WebError::UnnecessaryHousingInfo),
+            (_, None) => return Err(WebError::MissingHousingInfo),
+            (HousingKind::Interior, Some(plot)) => (1..=MAX_PLOT).contains(&plot),
+            (HousingKind::Lobby, Some(plot)) => plot == APARTMENT || plot == APARTMENT + APARTMENT_WING,
+            (HousingKind::Apartment, Some(plot)) => {
+                let room = if plot > APARTMENT + APARTMENT_WING {
+                    plot - APARTMENT - APARTMENT_WING
+                } else {
+                    plot.saturating_sub(APARTMENT)
+                };
+
+                (1..=MAX_ROOM).contains(&room)
+            }
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(WebError::InvalidHousingInfo("plot"))
+        }
+    }
+}
+
+impl State {
+    pub async fn update_territories(&self) -> Result<()> {
+        let text = tokio::fs::read_to_string(&self.config.territories)
+            .await
+            .with_context(|| format!("could not read territories at {:?}", self.config.territories))?;
+        let list: Vec<Territory> = serde_yaml::from_str(&text)
+            .context("could not parse territories")?;
+
+        let territories: HashMap<u32, Territory> = list.into_iter()
+            .map(|territory| (territory.id, territory))
+            .collect();
+        println!("added {} territories", territories.len());
+
+        *self.territories.write().await = territories;
+
+        Ok(())
+    }
+
+    pub async fn territory(&self, id: u32) -> Territory {
+        self.territories.read()
+            .await
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| Territory::unknown(id))
+    }
+
+    /// Checks that a territory is listed or has messages. Open world
+    /// territories aren't listed, so the messages are what make them known.
+    pub async fn is_known_territory(&self, id: u32) -> Result<bool> {
+        if self.territories.read().await.contains_key(&id) {
+            return Ok(true);
+        }
+
+        sqlx::query_scalar!(
+            // language=sqlite
+            r#"select exists(select 1 from messages where territory = ?) as "exists!: bool""#,
+            id,
+        )
+            .fetch_one(&self.db)
+            .await
+            .context("could not check for messages in territory")
+    }
+}
@@ -0,0 +1,129 @@
+use warp::http::StatusCode;
+
+use super::*;
+
+#[tokio::test]
+async fn register_returns_plain_token() {
+    let state = state().await;
+    let token = register(&state).await;
+    assert_eq!(token.len(), 32);
+
+    let response = get(&state, &token, "/account").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(&response)["messages"], 0);
+    assert_eq!(json(&response)["limit"], 3);
+}
+
+#[tokio::test]
+async fn register_with_recovery_returns_json() {
+    let state = state().await;
+    let response = send(&state, warp::test::request().method("POST").path("/account?recovery=true")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let credentials = json(&response);
+    assert!(credentials["auth"].is_string());
+    assert!(credentials["recovery"].is_string());
+}
+
+#[tokio::test]
+async fn missing_token_is_rejected() {
+    let state = state().await;
+    let response = send(&state, warp::test::request().path("/account")).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "missing_auth_token");
+}
+
+#[tokio::test]
+async fn invalid_token_is_rejected() {
+    let state = state().await;
+    let response = get(&state, "not a token", "/account").await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_auth_token");
+}
+
+#[tokio::test]
+async fn unregister_removes_account_and_messages() {
+    let state = state().await;
+    let author = register(&state).await;
+    let other = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    let response = delete(&state, &author, "/account").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = get(&state, &author, "/account").await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_auth_token");
+
+    let response = get(&state, &other, &format!("/messages/{id}")).await;
+    assert_error(&response, StatusCode::NOT_FOUND, "no_such_message");
+}
+
+#[tokio::test]
+async fn last_token_cannot_be_revoked() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let response = get(&state, &token, "/account/tokens").await;
+    let id = json(&response)[0]["id"].as_i64().unwrap();
+
+    let response = delete(&state, &token, &format!("/account/tokens/{id}")).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "cannot_revoke_last_token");
+
+    let response = get(&state, &token, "/account").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // once another device is linked the first token can go
+    let response = send(&state, warp::test::request().method("POST").path("/account/links").header("x-api-key", &token)).await;
+    let code = String::from_utf8(response.body().to_vec()).unwrap();
+    let response = send(
+        &state,
+        warp::test::request()
+            .method("POST")
+            .path("/account/links/redeem")
+            .json(&json!({ "code": code, "label": "laptop" })),
+    ).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = delete(&state, &token, &format!("/account/tokens/{id}")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn packs_only_lists_visible_packs() {
+    let state = state().await;
+    let response = send(&state, warp::test::request().path("/packs")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let packs = json(&response);
+    let ids: Vec<_> = packs.as_array().unwrap().iter().map(|pack| pack["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec![TEST_PACK]);
+}
+
+#[tokio::test]
+async fn prune_keeps_users_with_entitlements() {
+    let state = state().await;
+    let idle = register(&state).await;
+    let supporter = register(&state).await;
+    let supporter_id = user_id(&state, &supporter).await;
+
+    sqlx::query!(
+        // language=sqlite
+        "insert into user_entitlements (user, entitlement) values (?, 'supporter')",
+        supporter_id,
+    )
+        .execute(&state.db)
+        .await
+        .unwrap();
+    sqlx::query!(
+        // language=sqlite
+        "update users set last_seen = datetime(current_timestamp, '-30 days')",
+    )
+        .execute(&state.db)
+        .await
+        .unwrap();
+
+    crate::maintenance::prune(&state.db, Some(7)).await.unwrap();
+
+    let response = get(&state, &idle, "/account").await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_auth_token");
+    let response = get(&state, &supporter, "/account").await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,77 @@
+use warp::http::StatusCode;
+
+use super::*;
+
+async fn add_code(state: &Arc<State>, code: &str, extra: i64, uses: i64) {
+    sqlx::query!(
+        // language=sqlite
+        "insert into extra_tokens (id, extra, uses) values (?, ?, ?)",
+        code,
+        extra,
+        uses,
+    )
+        .execute(&state.db)
+        .await
+        .unwrap();
+}
+
+async fn claim(state: &Arc<State>, token: &str, code: &str) -> Response<Bytes> {
+    send(
+        state,
+        warp::test::request()
+            .method("POST")
+            .path("/claim")
+            .header("x-api-key", token)
+            .body(code),
+    ).await
+}
+
+#[tokio::test]
+async fn claim_adds_extra_messages() {
+    let state = state().await;
+    let token = register(&state).await;
+    add_code(&state, "EXTRA", 2, 1).await;
+
+    let response = claim(&state, &token, "EXTRA").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().as_ref(), b"2");
+
+    let response = get(&state, &token, "/account").await;
+    assert_eq!(json(&response)["limit"], 5);
+}
+
+#[tokio::test]
+async fn claim_rejects_unknown_code() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let response = claim(&state, &token, "NOPE").await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_extra_code");
+}
+
+#[tokio::test]
+async fn claim_rejects_code_used_twice() {
+    let state = state().await;
+    let token = register(&state).await;
+    add_code(&state, "MANY", 1, -1).await;
+
+    let response = claim(&state, &token, "MANY").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = claim(&state, &token, "MANY").await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_extra_code");
+}
+
+#[tokio::test]
+async fn claim_expends_single_use_code() {
+    let state = state().await;
+    let first = register(&state).await;
+    let second = register(&state).await;
+    add_code(&state, "ONCE", 1, 1).await;
+
+    let response = claim(&state, &first, "ONCE").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = claim(&state, &second, "ONCE").await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_extra_code");
+}
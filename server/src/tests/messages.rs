@@ -0,0 +1,389 @@
+use serde_json::json;
+use warp::http::StatusCode;
+
+use super::*;
+
+#[tokio::test]
+async fn write_formats_message() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let mut body = message(OPEN_WORLD, 0.0, 0.0);
+    body["conjunction"] = json!(0);
+    body["template_2"] = json!(2);
+    body["word_2_word"] = json!(1);
+    let id = write_ok(&state, &token, &body).await;
+
+    let response = get(&state, &token, &format!("/messages/{id}")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(&response)["message"], "treasure ahead\nand then Visit Limsa Lominsa");
+}
+
+#[tokio::test]
+async fn write_rejects_unknown_pack() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let mut body = message(OPEN_WORLD, 0.0, 0.0);
+    body["pack_id"] = json!("00000000-0000-0000-0000-000000000000");
+    let response = write(&state, &token, &body).await;
+    assert_error(&response, StatusCode::NOT_FOUND, "invalid_pack_id");
+}
+
+#[tokio::test]
+async fn write_rejects_out_of_range_index() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let mut body = message(OPEN_WORLD, 0.0, 0.0);
+    body["word_1_word"] = json!(99);
+    let response = write(&state, &token, &body).await;
+    assert_error(&response, StatusCode::NOT_FOUND, "invalid_index");
+}
+
+#[tokio::test]
+async fn write_rejects_invalid_body() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let response = write(&state, &token, &json!({ "territory": OPEN_WORLD })).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_body");
+}
+
+#[tokio::test]
+async fn write_enforces_message_limit() {
+    let state = state().await;
+    let token = register(&state).await;
+    for i in 0..3 {
+        write_ok(&state, &token, &message(OPEN_WORLD, i as f32 * 10.0, 0.0)).await;
+    }
+
+    let response = write(&state, &token, &message(OPEN_WORLD, 100.0, 0.0)).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "too_many_messages");
+}
+
+#[tokio::test]
+async fn write_requires_housing_info_in_housing() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let response = write(&state, &token, &message(HOUSING, 0.0, 0.0)).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "missing_housing_info");
+}
+
+#[tokio::test]
+async fn write_rejects_housing_info_outside_housing() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let mut body = message(OPEN_WORLD, 0.0, 0.0);
+    body["ward"] = json!(1);
+    let response = write(&state, &token, &body).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "unnecessary_housing_info");
+}
+
+#[tokio::test]
+async fn write_rejects_unknown_glyph() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let mut body = message(OPEN_WORLD, 0.0, 0.0);
+    body["glyph"] = json!(100);
+    let response = write(&state, &token, &body).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_glyph");
+}
+
+#[tokio::test]
+async fn write_rejects_stacked_messages() {
+    let state = state().await;
+    let token = register(&state).await;
+    write_ok(&state, &token, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    let response = write(&state, &token, &message(OPEN_WORLD, 0.5, 0.0)).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "too_close_to_own_message");
+}
+
+#[tokio::test]
+async fn write_rejects_stacking_across_worlds() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    // without a scope every world sees both messages
+    let mut first = message(OPEN_WORLD, 0.0, 0.0);
+    first["world"] = json!(73);
+    write_ok(&state, &token, &first).await;
+
+    let mut second = message(OPEN_WORLD, 0.5, 0.0);
+    second["world"] = json!(35);
+    let response = write(&state, &token, &second).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "too_close_to_own_message");
+}
+
+#[tokio::test]
+async fn write_rejects_stacking_within_scope() {
+    let state = state_with(r#"scope = "data_center""#).await;
+    let token = register(&state).await;
+
+    // adamantoise and jenova are both on aether
+    let mut first = message(OPEN_WORLD, 0.0, 0.0);
+    first["world"] = json!(73);
+    write_ok(&state, &token, &first).await;
+
+    let mut second = message(OPEN_WORLD, 0.5, 0.0);
+    second["world"] = json!(40);
+    let response = write(&state, &token, &second).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "too_close_to_own_message");
+
+    // famfrit is on primal, which never sees the first message
+    second["world"] = json!(35);
+    write_ok(&state, &token, &second).await;
+}
+
+#[tokio::test]
+async fn write_rejects_far_positions() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let response = write(&state, &token, &message(OPEN_WORLD, 1e30, 0.0)).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_position");
+}
+
+#[tokio::test]
+async fn concurrent_writes_respect_message_limit() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let mut writes = tokio::task::JoinSet::new();
+    for i in 0..6 {
+        let state = Arc::clone(&state);
+        let token = token.clone();
+        writes.spawn(async move {
+            write(&state, &token, &message(OPEN_WORLD, i as f32 * 10.0, 0.0)).await.status()
+        });
+    }
+
+    let mut written = 0;
+    while let Some(status) = writes.join_next().await {
+        if status.unwrap() == StatusCode::OK {
+            written += 1;
+        }
+    }
+    assert_eq!(written, 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_location_only_returns_territory() {
+    let state = state().await;
+    let token = register(&state).await;
+    let id = write_ok(&state, &token, &message(OPEN_WORLD, 0.0, 0.0)).await;
+    write_ok(&state, &token, &message(OPEN_WORLD + 1, 0.0, 0.0)).await;
+
+    let visible = ids(&state, &token, &format!("/messages/{OPEN_WORLD}")).await;
+    assert_eq!(visible, vec![id]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_location_filters_by_proximity() {
+    let state = state().await;
+    let token = register(&state).await;
+    let near = write_ok(&state, &token, &message(OPEN_WORLD, 0.0, 0.0)).await;
+    write_ok(&state, &token, &message(OPEN_WORLD, 100.0, 100.0)).await;
+
+    let visible = ids(&state, &token, &format!("/messages/{OPEN_WORLD}?x=1&z=1&radius=10")).await;
+    assert_eq!(visible, vec![near]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_location_rejects_partial_proximity() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let response = get(&state, &token, &format!("/messages/{OPEN_WORLD}?x=1")).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_proximity");
+}
+
+#[tokio::test]
+async fn get_mine_pages_through_messages() {
+    let state = state().await;
+    let token = register(&state).await;
+    for i in 0..3 {
+        write_ok(&state, &token, &message(OPEN_WORLD, i as f32 * 10.0, 0.0)).await;
+    }
+
+    let response = get(&state, &token, "/messages?v=2&limit=2").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let page = json(&response);
+    assert_eq!(page["messages"].as_array().unwrap().len(), 2);
+    assert_eq!(page["extra"], 0);
+
+    let next = page["next"].as_str().unwrap();
+    let response = get(&state, &token, &format!("/messages?v=2&limit=2&cursor={next}")).await;
+    let page = json(&response);
+    assert_eq!(page["messages"].as_array().unwrap().len(), 1);
+    assert!(page["next"].is_null());
+}
+
+#[tokio::test]
+async fn get_mine_rejects_unknown_cursor() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let response = get(&state, &token, "/messages?v=2&cursor=nothing").await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_cursor");
+}
+
+#[tokio::test]
+async fn get_mine_cursor_survives_deleted_message() {
+    let state = state().await;
+    let token = register(&state).await;
+    for i in 0..3 {
+        write_ok(&state, &token, &message(OPEN_WORLD, i as f32 * 10.0, 0.0)).await;
+    }
+
+    let response = get(&state, &token, "/messages?v=2&sort=score&limit=1").await;
+    let page = json(&response);
+    let first = page["messages"][0]["id"].as_str().unwrap().to_string();
+    let next = page["next"].as_str().unwrap().to_string();
+
+    delete(&state, &token, &format!("/messages/{first}")).await;
+
+    let mut seen = vec![first];
+    let mut cursor = Some(next);
+    while let Some(next) = cursor {
+        let response = get(&state, &token, &format!("/messages?v=2&sort=score&limit=1&cursor={next}")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = json(&response);
+        for message in page["messages"].as_array().unwrap() {
+            seen.push(message["id"].as_str().unwrap().to_string());
+        }
+        cursor = page["next"].as_str().map(ToOwned::to_owned);
+    }
+
+    let mut unique = seen.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(seen.len(), 3);
+    assert_eq!(unique.len(), 3);
+}
+
+#[tokio::test]
+async fn get_mine_rejects_invalid_query() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    for query in ["v=abc", "sort=foo", "limit=-1"] {
+        let response = get(&state, &token, &format!("/messages?{query}")).await;
+        assert_error(&response, StatusCode::BAD_REQUEST, "invalid_query");
+    }
+}
+
+#[tokio::test]
+async fn get_mine_cursor_is_tied_to_its_sort() {
+    let state = state().await;
+    let token = register(&state).await;
+    for i in 0..2 {
+        write_ok(&state, &token, &message(OPEN_WORLD, i as f32 * 10.0, 0.0)).await;
+    }
+
+    // a zero limit still returns a page and a cursor
+    let response = get(&state, &token, "/messages?v=2&sort=score&limit=0").await;
+    let page = json(&response);
+    assert_eq!(page["messages"].as_array().unwrap().len(), 1);
+    let next = page["next"].as_str().unwrap().to_string();
+
+    let response = get(&state, &token, &format!("/messages?v=2&sort=created&limit=1&cursor={next}")).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_cursor");
+}
+
+#[tokio::test]
+async fn stored_emotes_follow_configured_appearance() {
+    let state = state_with("[emotes]\nappearance = \"strip\"").await;
+    let token = register(&state).await;
+    let id = write_ok(&state, &token, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    // an emote stored while the server kept appearances
+    let equipment = json!({ "id": 1, "variant": 1, "stain_0": 2, "stain_1": 3 });
+    let emote: crate::emote::EmoteData = serde_json::from_value(json!({
+        "id": 1,
+        "customise": vec![1; crate::emote::CUSTOMISE_LEN],
+        "equipment": vec![equipment; crate::emote::EQUIPMENT_SLOTS],
+        "weapon": [],
+        "glasses": 5,
+        "hat_hidden": false,
+        "visor_toggled": false,
+        "weapon_hidden": false,
+    })).unwrap();
+    let bytes = emote.to_bytes();
+    sqlx::query!(
+        // language=sqlite
+        "update messages set emote = ? where id = ?",
+        bytes,
+        id,
+    )
+        .execute(&state.db)
+        .await
+        .unwrap();
+
+    let response = get(&state, &token, &format!("/messages/{id}")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let emote = &json(&response)["emote"];
+    assert_eq!(emote["id"], 1);
+    assert_eq!(emote["customise"], json!([]));
+    assert_eq!(emote["equipment"], json!([]));
+    assert_eq!(emote["glasses"], 0);
+}
+
+#[tokio::test]
+async fn erase_removes_message() {
+    let state = state().await;
+    let token = register(&state).await;
+    let id = write_ok(&state, &token, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    let response = delete(&state, &token, &format!("/messages/{id}")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = get(&state, &token, &format!("/messages/{id}")).await;
+    assert_error(&response, StatusCode::NOT_FOUND, "no_such_message");
+}
+
+#[tokio::test]
+async fn erase_ignores_other_users_messages() {
+    let state = state().await;
+    let author = register(&state).await;
+    let other = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    delete(&state, &other, &format!("/messages/{id}")).await;
+
+    let response = get(&state, &author, &format!("/messages/{id}")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn grid_hides_sparse_buckets() {
+    let state = state().await;
+    let token = register(&state).await;
+    for i in 0..3 {
+        write_ok(&state, &token, &message(OPEN_WORLD, i as f32 * 10.0, 0.0)).await;
+    }
+    let other = register(&state).await;
+    write_ok(&state, &other, &message(OPEN_WORLD, 300.0, 300.0)).await;
+
+    // too small a size is raised to the minimum
+    let response = get(&state, &token, &format!("/stats/territories/{OPEN_WORLD}/grid?size=10")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let grid = json(&response);
+    let grid = grid.as_array().unwrap();
+    assert_eq!(grid.len(), 1);
+    assert_eq!(grid[0]["x"], 0.0);
+    assert_eq!(grid[0]["count"], 3);
+}
+
+#[tokio::test]
+async fn grid_rejects_unknown_territory() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let response = get(&state, &token, "/stats/territories/999999/grid").await;
+    assert_error(&response, StatusCode::NOT_FOUND, "invalid_territory");
+}
@@ -0,0 +1,203 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use serde_json::{json, Value};
+use sqlx::{Pool, Sqlite};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use warp::http::{Response, StatusCode};
+use warp::test::RequestBuilder;
+
+use crate::{MIGRATOR, State, web};
+use crate::config::Config;
+
+mod accounts;
+mod claim;
+mod messages;
+mod pack;
+mod query;
+mod visibility;
+mod votes;
+
+pub const TEST_PACK: &str = "5a1c3b1e-7f0e-4d2b-9b7a-0c6e4f3d2a10";
+pub const OPEN_WORLD: u32 = 132;
+pub const HOUSING: u32 = 339;
+
+pub async fn pool() -> Pool<Sqlite> {
+    // an in-memory database only lives as long as its connection
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(SqliteConnectOptions::from_str("sqlite::memory:").unwrap().foreign_keys(true))
+        .await
+        .expect("could not open in-memory database");
+    MIGRATOR.run(&pool)
+        .await
+        .expect("could not run migrations");
+    pool
+}
+
+pub async fn state() -> Arc<State> {
+    state_with("").await
+}
+
+/// Creates a state with extra top-level config options.
+pub async fn state_with(options: &str) -> Arc<State> {
+    let dir = env!("CARGO_MANIFEST_DIR");
+    let config: Config = toml::from_str(&format!(
+        r#"
+            address = "127.0.0.1:0"
+            packs = "{dir}/src/tests/fixtures/packs"
+            territories = "{dir}/territories.yaml"
+            worlds = "{dir}/worlds.yaml"
+            glyphs = "{dir}/glyphs.yaml"
+            database = ":memory:"
+            vote_threshold_hide = -1
+            max_messages = 3
+            {options}
+
+            [voting]
+            min_account_age_hours = 0
+            min_messages = 0
+        "#,
+    ))
+        .expect("test config is valid");
+
+    let state = State::new(config, pool().await);
+    state.update_packs()
+        .await
+        .expect("could not load packs");
+    state.update_territories()
+        .await
+        .expect("could not load territories");
+    state.update_worlds()
+        .await
+        .expect("could not load worlds");
+    state.update_glyphs()
+        .await
+        .expect("could not load glyphs");
+
+    Arc::new(state)
+}
+
+pub async fn send(state: &Arc<State>, request: RequestBuilder) -> Response<Bytes> {
+    request.reply(&web::routes(Arc::clone(state))).await
+}
+
+pub fn json(response: &Response<Bytes>) -> Value {
+    serde_json::from_slice(response.body()).expect("response was not json")
+}
+
+pub fn assert_error(response: &Response<Bytes>, status: StatusCode, code: &str) {
+    assert_eq!(response.status(), status, "{:?}", response.body());
+    assert_eq!(json(response)["code"], code);
+}
+
+pub async fn register(state: &Arc<State>) -> String {
+    let response = send(state, warp::test::request().method("POST").path("/account")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    String::from_utf8(response.body().to_vec()).unwrap()
+}
+
+pub fn message(territory: u32, x: f32, z: f32) -> Value {
+    json!({
+        "territory": territory,
+        "x": x,
+        "y": 0.0,
+        "z": z,
+        "pack_id": TEST_PACK,
+        "template_1": 0,
+        "word_1_list": 0,
+        "word_1_word": 0,
+    })
+}
+
+pub fn housing_message(x: f32, z: f32) -> Value {
+    let mut message = message(HOUSING, x, z);
+    message["world"] = json!(73);
+    message["ward"] = json!(1);
+    message
+}
+
+pub async fn write(state: &Arc<State>, token: &str, message: &Value) -> Response<Bytes> {
+    send(
+        state,
+        warp::test::request()
+            .method("POST")
+            .path("/messages")
+            .header("x-api-key", token)
+            .json(message),
+    ).await
+}
+
+pub async fn write_ok(state: &Arc<State>, token: &str, message: &Value) -> String {
+    let response = write(state, token, message).await;
+    assert_eq!(response.status(), StatusCode::OK, "{:?}", response.body());
+    String::from_utf8(response.body().to_vec()).unwrap()
+}
+
+pub async fn get(state: &Arc<State>, token: &str, path: &str) -> Response<Bytes> {
+    send(
+        state,
+        warp::test::request()
+            .path(path)
+            .header("x-api-key", token),
+    ).await
+}
+
+pub async fn delete(state: &Arc<State>, token: &str, path: &str) -> Response<Bytes> {
+    send(
+        state,
+        warp::test::request()
+            .method("DELETE")
+            .path(path)
+            .header("x-api-key", token),
+    ).await
+}
+
+pub async fn vote(state: &Arc<State>, token: &str, id: &str, vote: i8) -> Response<Bytes> {
+    send(
+        state,
+        warp::test::request()
+            .method("PATCH")
+            .path(&format!("/messages/{id}/votes"))
+            .header("x-api-key", token)
+            .json(&vote),
+    ).await
+}
+
+pub async fn ids(state: &Arc<State>, token: &str, path: &str) -> Vec<String> {
+    let response = get(state, token, path).await;
+    assert_eq!(response.status(), StatusCode::OK, "{:?}", response.body());
+    json(&response)
+        .as_array()
+        .expect("response was not an array")
+        .iter()
+        .map(|message| message["id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+pub async fn user_id(state: &Arc<State>, token: &str) -> i64 {
+    let hashed = crate::util::hash(token);
+    sqlx::query_scalar!(
+        // language=sqlite
+        "select user from auth_tokens where hash = ?",
+        hashed,
+    )
+        .fetch_one(&state.db)
+        .await
+        .unwrap()
+}
+
+pub async fn shadowban(state: &Arc<State>, token: &str) {
+    let id = user_id(state, token).await;
+    sqlx::query!(
+        // language=sqlite
+        "update users set shadowbanned = true where id = ?",
+        id,
+    )
+        .execute(&state.db)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,152 @@
+use std::sync::OnceLock;
+
+use proptest::prelude::*;
+
+use crate::pack::{Pack, Template};
+
+fn packs() -> &'static [Pack] {
+    static PACKS: OnceLock<Vec<Pack>> = OnceLock::new();
+    PACKS.get_or_init(|| {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/packs");
+        let mut packs = Vec::new();
+        for entry in std::fs::read_dir(dir).expect("could not read packs") {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|x| x.to_str()) != Some("yaml") {
+                continue;
+            }
+
+            let text = std::fs::read_to_string(&path).unwrap();
+            let pack = serde_yaml::from_str(&text)
+                .unwrap_or_else(|e| panic!("could not parse {path:?}: {e}"));
+            packs.push(pack);
+        }
+
+        assert!(!packs.is_empty());
+        packs
+    })
+}
+
+/// Picks a word for the template the same way clients do, reducing the seeds
+/// into range.
+fn valid_word(pack: &Pack, template: &Template, list_seed: usize, word_seed: usize) -> Option<(usize, usize)> {
+    if !template.requires_word() {
+        return None;
+    }
+
+    match template {
+        Template::List { words, .. } => Some((0, word_seed % words.len())),
+        Template::Basic(_) => {
+            let lists = pack.words.as_ref().expect("pack has templates needing words but no words");
+            let list_idx = list_seed % lists.len();
+            Some((list_idx, word_seed % lists[list_idx].words.len()))
+        }
+    }
+}
+
+/// Gets a word index that is out of range for a template that needs a word.
+fn invalid_word(pack: &Pack, template: &Template, list_seed: usize, word_seed: usize, list_out: bool) -> (usize, usize) {
+    let extra = word_seed % 16;
+    match template {
+        Template::List { words, .. } => (0, words.len() + extra),
+        Template::Basic(_) => {
+            let lists = pack.words.as_ref().unwrap();
+            if list_out {
+                (lists.len() + extra, 0)
+            } else {
+                let list_idx = list_seed % lists.len();
+                (list_idx, lists[list_idx].words.len() + extra)
+            }
+        }
+    }
+}
+
+fn index() -> impl Strategy<Value = usize> {
+    prop_oneof![0..512usize, any::<usize>()]
+}
+
+proptest! {
+    #[test]
+    fn format_never_panics(
+        template_1 in index(),
+        word_1 in proptest::option::of((index(), index())),
+        conjunction in proptest::option::of(index()),
+        template_2 in proptest::option::of(index()),
+        word_2 in proptest::option::of((index(), index())),
+    ) {
+        for pack in packs() {
+            let _ = pack.format(template_1, word_1, conjunction, template_2, word_2);
+        }
+    }
+
+    #[test]
+    fn valid_indices_render_completely(seeds in any::<[usize; 7]>(), two_parts: bool) {
+        for pack in packs() {
+            let template_1 = &pack.templates[seeds[0] % pack.templates.len()];
+            let word_1 = valid_word(pack, template_1, seeds[1], seeds[2]);
+
+            let (conjunction, template_2, word_2) = match (&pack.conjunctions, two_parts) {
+                (Some(conjunctions), true) => {
+                    let template_2 = seeds[4] % pack.templates.len();
+                    let word_2 = valid_word(pack, &pack.templates[template_2], seeds[5], seeds[6]);
+                    (Some(seeds[3] % conjunctions.len()), Some(template_2), word_2)
+                }
+                _ => (None, None, None),
+            };
+
+            let formatted = pack.format(seeds[0] % pack.templates.len(), word_1, conjunction, template_2, word_2);
+            let formatted = formatted.unwrap_or_else(|| panic!("{} rejected valid indices", pack.name));
+            prop_assert!(!formatted.contains("{0}"), "{}: {formatted:?}", pack.name);
+
+            if let (Some(conjunctions), Some(conjunction)) = (&pack.conjunctions, conjunction) {
+                let conj = &conjunctions[conjunction];
+                let is_punc = conj.len() == 1 && conj.chars().all(|c| c.is_ascii_punctuation());
+                if is_punc {
+                    prop_assert!(formatted.contains(&format!("{conj}\n")), "{}: {formatted:?}", pack.name);
+                } else {
+                    prop_assert!(formatted.contains(&format!("\n{conj} ")), "{}: {formatted:?}", pack.name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range_template_is_rejected(extra in 0..64usize) {
+        for pack in packs() {
+            prop_assert!(pack.format(pack.templates.len() + extra, Some((0, 0)), None, None, None).is_none());
+        }
+    }
+
+    #[test]
+    fn out_of_range_word_is_rejected(seeds in any::<[usize; 3]>(), list_out: bool) {
+        for pack in packs() {
+            let template = seeds[0] % pack.templates.len();
+            if !pack.templates[template].requires_word() {
+                continue;
+            }
+
+            let word = invalid_word(pack, &pack.templates[template], seeds[1], seeds[2], list_out);
+            prop_assert!(pack.format(template, Some(word), None, None, None).is_none());
+            prop_assert!(pack.format(template, None, None, None, None).is_none());
+        }
+    }
+
+    #[test]
+    fn out_of_range_second_part_is_rejected(seeds in any::<[usize; 5]>(), extra in 0..64usize) {
+        for pack in packs() {
+            let Some(conjunctions) = &pack.conjunctions else {
+                continue;
+            };
+
+            let template_1 = seeds[0] % pack.templates.len();
+            let word_1 = valid_word(pack, &pack.templates[template_1], seeds[1], seeds[2]);
+            let template_2 = seeds[3] % pack.templates.len();
+            let word_2 = valid_word(pack, &pack.templates[template_2], seeds[4], seeds[0]);
+
+            let bad_conjunction = pack.format(template_1, word_1, Some(conjunctions.len() + extra), Some(template_2), word_2);
+            prop_assert!(bad_conjunction.is_none());
+
+            let bad_template = pack.format(template_1, word_1, Some(0), Some(pack.templates.len() + extra), word_2);
+            prop_assert!(bad_template.is_none());
+        }
+    }
+}
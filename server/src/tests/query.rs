@@ -0,0 +1,108 @@
+use sqlx::{Pool, Sqlite};
+
+use crate::message::RetrievedMessage;
+use crate::query::MessageQuery;
+use crate::tests::pool;
+
+const OPEN_WORLD: i64 = 132;
+const HOUSING: i64 = 339;
+
+async fn user(db: &Pool<Sqlite>, shadowbanned: bool) -> i64 {
+    // language=sqlite
+    sqlx::query_scalar("insert into users (shadowbanned) values (?) returning id")
+        .bind(shadowbanned)
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+async fn message(db: &Pool<Sqlite>, id: &str, user: i64, territory: i64, housing: Option<(u32, u32, u32)>) {
+    let (world, ward, plot) = match housing {
+        Some((world, ward, plot)) => (Some(world), Some(ward), Some(plot)),
+        None => (None, None, None),
+    };
+
+    // language=sqlite
+    sqlx::query("insert into messages (id, user, territory, world, ward, plot, glyph, x, y, z, yaw, message) values (?, ?, ?, ?, ?, ?, 0, 0, 0, 0, 0, 'test')")
+        .bind(id)
+        .bind(user)
+        .bind(territory)
+        .bind(world)
+        .bind(ward)
+        .bind(plot)
+        .execute(db)
+        .await
+        .unwrap();
+}
+
+async fn ids(query: MessageQuery, db: &Pool<Sqlite>) -> Vec<String> {
+    let mut ids: Vec<String> = query.fetch_all::<RetrievedMessage>(db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|message| message.id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[tokio::test]
+async fn shadowbanned_hidden_in_open_world() {
+    let db = pool().await;
+    let banned = user(&db, true).await;
+    let normal = user(&db, false).await;
+    message(&db, "a", banned, OPEN_WORLD, None).await;
+    message(&db, "b", normal, OPEN_WORLD, None).await;
+
+    let seen = ids(MessageQuery::new(normal).territory(OPEN_WORLD).visible(), &db).await;
+    assert_eq!(seen, ["b"]);
+
+    // the author still sees their own message
+    let seen = ids(MessageQuery::new(banned).territory(OPEN_WORLD).visible(), &db).await;
+    assert_eq!(seen, ["a", "b"]);
+}
+
+#[tokio::test]
+async fn shadowbanned_hidden_in_housing() {
+    let db = pool().await;
+    let banned = user(&db, true).await;
+    let normal = user(&db, false).await;
+    message(&db, "a", banned, HOUSING, Some((73, 1, 1))).await;
+    message(&db, "b", normal, HOUSING, Some((73, 1, 1))).await;
+    message(&db, "c", normal, HOUSING, Some((73, 2, 1))).await;
+
+    let query = MessageQuery::new(normal)
+        .territory(HOUSING)
+        .housing(Some(73), Some(1), Some(1))
+        .visible();
+    assert_eq!(ids(query, &db).await, ["b"]);
+
+    let query = MessageQuery::new(banned)
+        .territory(HOUSING)
+        .housing(Some(73), Some(1), Some(1))
+        .visible();
+    assert_eq!(ids(query, &db).await, ["a", "b"]);
+}
+
+#[tokio::test]
+async fn unfiltered_query_includes_shadowbanned() {
+    let db = pool().await;
+    let banned = user(&db, true).await;
+    let normal = user(&db, false).await;
+    message(&db, "a", banned, OPEN_WORLD, None).await;
+
+    let found = MessageQuery::new(normal)
+        .id("a")
+        .fetch_optional::<RetrievedMessage>(&db)
+        .await
+        .unwrap();
+    assert!(found.is_some());
+
+    let found = MessageQuery::new(normal)
+        .id("a")
+        .visible()
+        .fetch_optional::<RetrievedMessage>(&db)
+        .await
+        .unwrap();
+    assert!(found.is_none());
+}
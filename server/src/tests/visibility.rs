@@ -0,0 +1,151 @@
+use warp::http::StatusCode;
+
+use super::*;
+
+const HOUSING_QUERY: &str = "?world=73&ward=1";
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shadowbanned_author_sees_own_open_world_messages() {
+    let state = state().await;
+    let author = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+    shadowban(&state, &author).await;
+
+    let ids = ids(&state, &author, &format!("/messages/{OPEN_WORLD}")).await;
+    assert_eq!(ids, vec![id]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shadowbanned_open_world_messages_are_hidden_from_others() {
+    let state = state().await;
+    let author = register(&state).await;
+    let other = register(&state).await;
+    write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+    let visible = write_ok(&state, &other, &message(OPEN_WORLD, 100.0, 100.0)).await;
+    shadowban(&state, &author).await;
+
+    let ids = ids(&state, &other, &format!("/messages/{OPEN_WORLD}")).await;
+    assert_eq!(ids, vec![visible]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shadowbanned_author_sees_own_housing_messages() {
+    let state = state().await;
+    let author = register(&state).await;
+    let id = write_ok(&state, &author, &housing_message(0.0, 0.0)).await;
+    shadowban(&state, &author).await;
+
+    let ids = ids(&state, &author, &format!("/messages/{HOUSING}{HOUSING_QUERY}")).await;
+    assert_eq!(ids, vec![id]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shadowbanned_housing_messages_are_hidden_from_others() {
+    let state = state().await;
+    let author = register(&state).await;
+    let other = register(&state).await;
+    write_ok(&state, &author, &housing_message(0.0, 0.0)).await;
+    let visible = write_ok(&state, &other, &housing_message(100.0, 100.0)).await;
+    shadowban(&state, &author).await;
+
+    let ids = ids(&state, &other, &format!("/messages/{HOUSING}{HOUSING_QUERY}")).await;
+    assert_eq!(ids, vec![visible]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn housing_messages_are_scoped_to_their_ward() {
+    let state = state().await;
+    let author = register(&state).await;
+    write_ok(&state, &author, &housing_message(0.0, 0.0)).await;
+
+    let ids = ids(&state, &author, &format!("/messages/{HOUSING}?world=73&ward=2")).await;
+    assert!(ids.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shadowbanned_message_by_id_is_only_visible_to_author() {
+    let state = state().await;
+    let author = register(&state).await;
+    let other = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+    shadowban(&state, &author).await;
+
+    let response = get(&state, &author, &format!("/messages/{id}")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(&response)["territory"], OPEN_WORLD);
+
+    let response = get(&state, &other, &format!("/messages/{id}")).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(json(&response)["code"], "no_such_message");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shadowbanned_author_sees_own_messages_in_list() {
+    let state = state().await;
+    let author = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+    shadowban(&state, &author).await;
+
+    let ids = ids(&state, &author, "/messages").await;
+    assert_eq!(ids, vec![id]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn known_messages_do_not_skip_density_filter() {
+    let state = state().await;
+    let mut all = Vec::new();
+    for author in 0..4 {
+        let token = register(&state).await;
+        for i in 0..3 {
+            let x = author as f32 * 0.25 + i as f32 * 2.0;
+            all.push(write_ok(&state, &token, &message(OPEN_WORLD, x, 0.0)).await);
+        }
+    }
+
+    let viewer = register(&state).await;
+    let mut shown = ids(&state, &viewer, &format!("/messages/{OPEN_WORLD}")).await;
+    shown.sort();
+    assert!(shown.len() < all.len());
+
+    // the same viewer is shown the same messages again
+    let mut again = ids(&state, &viewer, &format!("/messages/{OPEN_WORLD}")).await;
+    again.sort();
+    assert_eq!(shown, again);
+
+    // claiming to know every message doesn't make them all visible
+    let response = get(&state, &viewer, &format!("/messages/{OPEN_WORLD}?known={}", all.join(","))).await;
+    let delta = json(&response);
+    assert!(delta["added"].as_array().unwrap().is_empty());
+    let removed = delta["removed"].as_array().unwrap().len();
+    assert_eq!(removed, all.len() - shown.len());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scope_limits_open_world_messages() {
+    let state = state_with(r#"scope = "data_center""#).await;
+    let token = register(&state).await;
+
+    let response = write(&state, &token, &message(OPEN_WORLD, 0.0, 0.0)).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "missing_world");
+
+    let mut aether = message(OPEN_WORLD, 0.0, 0.0);
+    aether["world"] = json!(73);
+    let aether = write_ok(&state, &token, &aether).await;
+    let mut primal = message(OPEN_WORLD, 50.0, 0.0);
+    primal["world"] = json!(35);
+    let primal = write_ok(&state, &token, &primal).await;
+
+    // the author always passes the density filter
+    let visible = ids(&state, &token, &format!("/messages/{OPEN_WORLD}?world=40")).await;
+    assert_eq!(visible, vec![aether.clone()]);
+
+    // clients that don't send their world see every world
+    let mut visible = ids(&state, &token, &format!("/messages/{OPEN_WORLD}")).await;
+    visible.sort();
+    let mut all = vec![aether, primal];
+    all.sort();
+    assert_eq!(visible, all);
+
+    let response = get(&state, &token, "/stats/territories?world=40").await;
+    assert_eq!(json(&response)[0]["messages"], 1);
+}
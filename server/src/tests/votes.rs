@@ -0,0 +1,277 @@
+use warp::http::StatusCode;
+
+use super::*;
+
+#[tokio::test]
+async fn vote_is_counted() {
+    let state = state().await;
+    let author = register(&state).await;
+    let voter = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    let response = vote(&state, &voter, &id, 1).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = get(&state, &voter, &format!("/messages/{id}")).await;
+    let message = json(&response);
+    assert_eq!(message["positive_votes"], 1);
+    assert_eq!(message["negative_votes"], 0);
+    assert_eq!(message["user_vote"], 1);
+
+    // every route reads the same tally
+    let response = get(&state, &author, "/account").await;
+    assert_eq!(json(&response)["positive_votes"], 1);
+    let response = get(&state, &author, "/account/export").await;
+    assert_eq!(json(&response)["messages"][0]["positive_votes"], 1);
+}
+
+#[tokio::test]
+async fn vote_can_be_changed_and_withdrawn() {
+    let state = state().await;
+    let author = register(&state).await;
+    let voter = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    vote(&state, &voter, &id, 1).await;
+    vote(&state, &voter, &id, -1).await;
+
+    let response = get(&state, &voter, &format!("/messages/{id}")).await;
+    assert_eq!(json(&response)["positive_votes"], 0);
+    assert_eq!(json(&response)["negative_votes"], 1);
+
+    let response = delete(&state, &voter, &format!("/messages/{id}/votes")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = get(&state, &voter, &format!("/messages/{id}")).await;
+    assert_eq!(json(&response)["negative_votes"], 0);
+    assert_eq!(json(&response)["user_vote"], 0);
+}
+
+#[tokio::test]
+async fn vote_on_own_message_is_rejected() {
+    let state = state().await;
+    let author = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    let response = vote(&state, &author, &id, 1).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "cannot_vote_own_message");
+}
+
+#[tokio::test]
+async fn vote_on_missing_message_is_rejected() {
+    let state = state().await;
+    let voter = register(&state).await;
+
+    let response = vote(&state, &voter, "00000000000000000000000000000000", 1).await;
+    assert_error(&response, StatusCode::NOT_FOUND, "no_such_message");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn downvoted_messages_are_hidden_from_others() {
+    let state = state().await;
+    let author = register(&state).await;
+    let first = register(&state).await;
+    let second = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    vote(&state, &first, &id, -1).await;
+    vote(&state, &second, &id, -1).await;
+
+    let visible = ids(&state, &first, &format!("/messages/{OPEN_WORLD}")).await;
+    assert!(visible.is_empty());
+
+    let visible = ids(&state, &author, &format!("/messages/{OPEN_WORLD}")).await;
+    assert_eq!(visible, vec![id]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn vote_weights_are_not_rounded() {
+    let state = state().await;
+    let author = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    for _ in 0..3 {
+        let voter = register(&state).await;
+        vote(&state, &voter, &id, -1).await;
+    }
+
+    // three light votes outweigh the threshold of -1 even though each
+    // would round to nothing
+    sqlx::query!(
+        // language=sqlite
+        "update votes set weight = 0.4 where message = ?",
+        id,
+    )
+        .execute(&state.db)
+        .await
+        .unwrap();
+
+    let viewer = register(&state).await;
+    let visible = ids(&state, &viewer, &format!("/messages/{OPEN_WORLD}")).await;
+    assert!(visible.is_empty());
+
+    let response = get(&state, &author, &format!("/messages/{id}")).await;
+    assert_eq!(json(&response)["negative_votes"], 3);
+}
+
+#[tokio::test]
+async fn vote_weights_follow_standing() {
+    let state = state().await;
+    let author = register(&state).await;
+    let voter = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+    vote(&state, &voter, &id, 1).await;
+
+    sqlx::query!(
+        // language=sqlite
+        "update votes set weight = 0.5 where message = ?",
+        id,
+    )
+        .execute(&state.db)
+        .await
+        .unwrap();
+
+    let voter_id = user_id(&state, &voter).await;
+    state.update_vote_weights(voter_id).await.unwrap();
+
+    let weight = sqlx::query_scalar!(
+        // language=sqlite
+        "select weight from votes where message = ?",
+        id,
+    )
+        .fetch_one(&state.db)
+        .await
+        .unwrap();
+    assert_eq!(weight, 1.0);
+}
+
+#[tokio::test]
+async fn appraisals_sharing_a_timestamp_are_not_skipped() {
+    let state = state().await;
+    let author = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    for _ in 0..3 {
+        let voter = register(&state).await;
+        vote(&state, &voter, &id, 1).await;
+    }
+
+    sqlx::query!(
+        // language=sqlite
+        "update votes set created = '2024-01-01 00:00:00.000'",
+    )
+        .execute(&state.db)
+        .await
+        .unwrap();
+
+    let response = get(&state, &author, "/account/appraisals").await;
+    let page = json(&response);
+    assert_eq!(page["appraisals"].as_array().unwrap().len(), 3);
+
+    let response = get(&state, &author, &format!("/account/appraisals?cursor={}", page["next"].as_str().unwrap())).await;
+    assert!(json(&response)["appraisals"].as_array().unwrap().is_empty());
+
+    // a page that ended on the first of the three votes
+    let first = sqlx::query_scalar!(
+        // language=sqlite
+        r#"select min(rowid) as "rowid!: i64" from votes"#,
+    )
+        .fetch_one(&state.db)
+        .await
+        .unwrap();
+    let created = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    let cursor = crate::util::encode_cursor(&(created, first));
+
+    let response = get(&state, &author, &format!("/account/appraisals?cursor={cursor}")).await;
+    assert_eq!(json(&response)["appraisals"].as_array().unwrap().len(), 2);
+
+    let response = get(&state, &author, "/account/appraisals?cursor=nonsense").await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_cursor");
+}
+
+#[tokio::test]
+async fn top_follows_vote_changes() {
+    let state = state().await;
+    let author = register(&state).await;
+    let voter = register(&state).await;
+    let liked = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+    let disliked = write_ok(&state, &author, &message(OPEN_WORLD, 10.0, 0.0)).await;
+
+    vote(&state, &voter, &liked, 1).await;
+    vote(&state, &voter, &disliked, 1).await;
+    let response = vote(&state, &voter, &disliked, -1).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let top = ids(&state, &voter, &format!("/messages/top?window=all&min_votes=1&pack={TEST_PACK}")).await;
+    assert_eq!(top, vec![liked.clone()]);
+
+    // erasing a message takes its tally with it
+    delete(&state, &author, &format!("/messages/{liked}")).await;
+    let count = sqlx::query_scalar!(
+        // language=sqlite
+        r#"select count(*) as "count!: i64" from message_scores where message = ?"#,
+        liked,
+    )
+        .fetch_one(&state.db)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn top_rejects_unknown_pack() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let response = get(&state, &token, "/messages/top?pack=00000000-0000-0000-0000-000000000000").await;
+    assert_error(&response, StatusCode::NOT_FOUND, "invalid_pack_id");
+}
+
+#[tokio::test]
+async fn top_includes_messages_without_votes() {
+    let state = state().await;
+    let token = register(&state).await;
+    let id = write_ok(&state, &token, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    let top = ids(&state, &token, "/messages/top?window=all").await;
+    assert_eq!(top, vec![id]);
+}
+
+#[tokio::test]
+async fn top_rejects_unknown_territory_and_world() {
+    let state = state().await;
+    let token = register(&state).await;
+
+    let response = get(&state, &token, &format!("/messages/top?territory={OPEN_WORLD}")).await;
+    assert_error(&response, StatusCode::NOT_FOUND, "invalid_territory");
+
+    // open world territories are known once they have messages
+    write_ok(&state, &token, &message(OPEN_WORLD, 0.0, 0.0)).await;
+    let response = get(&state, &token, &format!("/messages/top?territory={OPEN_WORLD}")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = get(&state, &token, "/messages/top?world=999999").await;
+    assert_error(&response, StatusCode::NOT_FOUND, "invalid_world");
+
+    let response = get(&state, &token, "/stats/territories?world=999999").await;
+    assert_error(&response, StatusCode::NOT_FOUND, "invalid_world");
+}
+
+#[tokio::test]
+async fn events_mark_messages_voted_below_threshold() {
+    let state = state().await;
+    let author = register(&state).await;
+    let id = write_ok(&state, &author, &message(OPEN_WORLD, 0.0, 0.0)).await;
+
+    let mut events = state.events.subscribe();
+    for _ in 0..2 {
+        let voter = register(&state).await;
+        vote(&state, &voter, &id, -1).await;
+    }
+
+    let mut hidden = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        hidden.push(event.hidden);
+    }
+    assert_eq!(hidden, vec![false, true]);
+}
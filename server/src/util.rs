@@ -1,52 +1,27 @@
+use rand::distributions::{Alphanumeric, DistString};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use sha3::{Digest, Sha3_384};
 
-// TerritoryIntendedUse = 13 or 14
-pub const HOUSING_ZONES: &[u32] = &[
-    282,
-    283,
-    284,
-    339,
-    340,
-    341,
-    342,
-    343,
-    344,
-    345,
-    346,
-    347,
-    384,
-    385,
-    386,
-    423,
-    424,
-    425,
-    573,
-    574,
-    575,
-    608,
-    609,
-    610,
-    641,
-    649,
-    650,
-    651,
-    652,
-    653,
-    654,
-    655,
-    979,
-    980,
-    981,
-    982,
-    983,
-    984,
-    985,
-    999,
-];
-
 pub fn hash(input: &str) -> String {
     let mut hasher = Sha3_384::default();
     hasher.update(input.as_bytes());
     let result = hasher.finalize();
     data_encoding::BASE64.encode(&result)
 }
+
+pub fn generate_token() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 32)
+}
+
+/// Encodes the sort key of the last item on a page as an opaque cursor that
+/// the next page starts after.
+pub fn encode_cursor(key: &impl Serialize) -> String {
+    let json = serde_json::to_vec(key).unwrap_or_default();
+    data_encoding::BASE64URL_NOPAD.encode(&json)
+}
+
+pub fn decode_cursor<T: DeserializeOwned>(cursor: &str) -> Option<T> {
+    let json = data_encoding::BASE64URL_NOPAD.decode(cursor.as_bytes()).ok()?;
+    serde_json::from_slice(&json).ok()
+}
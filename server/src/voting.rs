@@ -0,0 +1,257 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::State;
+
+pub struct VoteWeight {
+    pub weight: f64,
+    pub nullified: bool,
+}
+
+/// A group of accounts that have repeatedly voted identically on the same
+/// author's messages.
+pub struct VotingRing {
+    pub author: i64,
+    pub voters: Vec<i64>,
+    /// the most identical votes cast by any two accounts in the ring
+    pub shared_votes: i64,
+}
+
+struct VotingPair {
+    author: i64,
+    voter_a: i64,
+    voter_b: i64,
+    shared_votes: i64,
+}
+
+impl State {
+    pub async fn vote_weight(&self, id: i64) -> Result<VoteWeight> {
+        let voter = sqlx::query!(
+            // language=sqlite
+            r#"
+                select u.vote_flagged                                                        as "vote_flagged: bool",
+                       cast((julianday(current_timestamp) - julianday(u.created)) * 24 as int) as "age_hours: i64",
+                       cast((julianday(u.last_seen) - julianday(u.created)) * 24 as int)       as "active_hours: i64",
+                       (select count(*) from messages m where m.user = u.id)                 as "messages!: i64"
+                from users u
+                where u.id = ?"#,
+            id,
+        )
+            .fetch_one(&self.db)
+            .await
+            .context("could not get voter from database")?;
+
+        let config = &self.config.voting;
+        // accounts from before registration dates were tracked are old enough
+        let old_enough = voter.age_hours.map(|age| age >= config.min_account_age_hours).unwrap_or(true);
+        let active = voter.messages >= config.min_messages
+            || voter.active_hours.map(|active| active >= config.min_active_hours).unwrap_or(true);
+
+        let weight = if old_enough && active {
+            1.0
+        } else {
+            config.unestablished_weight
+        };
+
+        Ok(VoteWeight {
+            weight,
+            nullified: voter.vote_flagged,
+        })
+    }
+
+    pub async fn set_vote_flagged(&self, id: i64, flagged: bool) -> Result<()> {
+        let mut t = self.db.begin()
+            .await
+            .context("could not start transaction")?;
+
+        sqlx::query!(
+            // language=sqlite
+            "update users set vote_flagged = ? where id = ?",
+            flagged,
+            id,
+        )
+            .execute(&mut *t)
+            .await
+            .context("could not update user")?;
+
+        // votes are kept so that unflagging an account restores them
+        sqlx::query!(
+            // language=sqlite
+            "update votes set nullified = ? where user = ?",
+            flagged,
+            id,
+        )
+            .execute(&mut *t)
+            .await
+            .context("could not update votes")?;
+
+        sqlx::query!(
+            // language=sqlite
+            r#"
+                update messages
+                set votes_updated = strftime('%Y-%m-%d %H:%M:%f', 'now')
+                where id in (select message from votes where user = ?)"#,
+            id,
+        )
+            .execute(&mut *t)
+            .await
+            .context("could not update messages")?;
+
+        t.commit()
+            .await
+            .context("could not commit transaction")?;
+
+        Ok(())
+    }
+
+    /// Recalculates the weight of a user's votes from their current standing.
+    pub async fn update_vote_weights(&self, id: i64) -> Result<()> {
+        let weight = self.vote_weight(id).await?.weight;
+
+        let mut t = self.db.begin()
+            .await
+            .context("could not start transaction")?;
+
+        sqlx::query!(
+            // language=sqlite
+            r#"
+                update messages
+                set votes_updated = strftime('%Y-%m-%d %H:%M:%f', 'now')
+                where id in (select message from votes where user = ? and weight != ?)"#,
+            id,
+            weight,
+        )
+            .execute(&mut *t)
+            .await
+            .context("could not update messages")?;
+
+        sqlx::query!(
+            // language=sqlite
+            "update votes set weight = ? where user = ? and weight != ?",
+            weight,
+            id,
+            weight,
+        )
+            .execute(&mut *t)
+            .await
+            .context("could not update votes")?;
+
+        t.commit()
+            .await
+            .context("could not commit transaction")?;
+
+        Ok(())
+    }
+
+    pub async fn detect_voting_rings(&self) -> Result<Vec<VotingRing>> {
+        let pairs = sqlx::query_as!(
+            VotingPair,
+            // language=sqlite
+            r#"
+                select m.user                  as "author!: i64",
+                       cast(v1.user as integer) as "voter_a!: i64",
+                       cast(v2.user as integer) as "voter_b!: i64",
+                       count(*)                as "shared_votes!: i64"
+                from votes v1
+                         inner join votes v2 on v1.message = v2.message and v1.vote = v2.vote and v1.user < v2.user
+                         inner join messages m on v1.message = m.id
+                where v1.vote != 0 and not v1.nullified and not v2.nullified
+                group by m.user, v1.user, v2.user
+                having count(*) >= ?"#,
+            self.config.voting.ring_min_shared_votes,
+        )
+            .fetch_all(&self.db)
+            .await
+            .context("could not detect voting rings")?;
+
+        // pairs that share an account on the same author are part of the
+        // same ring
+        let mut rings: Vec<VotingRing> = Vec::new();
+        for pair in pairs {
+            let (joined, mut rest): (Vec<_>, Vec<_>) = rings.into_iter()
+                .partition(|ring| ring.author == pair.author
+                    && (ring.voters.contains(&pair.voter_a) || ring.voters.contains(&pair.voter_b)));
+
+            let mut ring = VotingRing {
+                author: pair.author,
+                voters: vec![pair.voter_a, pair.voter_b],
+                shared_votes: pair.shared_votes,
+            };
+            for other in joined {
+                ring.voters.extend(other.voters);
+                ring.shared_votes = ring.shared_votes.max(other.shared_votes);
+            }
+            ring.voters.sort_unstable();
+            ring.voters.dedup();
+
+            rest.push(ring);
+            rings = rest;
+        }
+
+        rings.sort_unstable_by(|a, b| b.voters.len().cmp(&a.voters.len()).then(b.shared_votes.cmp(&a.shared_votes)));
+        Ok(rings)
+    }
+
+    async fn review_votes(&self) -> Result<()> {
+        // standing only changes with time and activity, so the weight of
+        // votes from accounts that were not established is checked again
+        let voters = sqlx::query_scalar!(
+            // language=sqlite
+            r#"select distinct cast(user as integer) as "user!: i64" from votes where weight != 1"#,
+        )
+            .fetch_all(&self.db)
+            .await
+            .context("could not get voters from database")?;
+
+        for voter in voters {
+            self.update_vote_weights(voter).await?;
+        }
+
+        for ring in self.detect_voting_rings().await? {
+            println!("{ring}");
+
+            if self.config.voting.flag_rings {
+                for &voter in &ring.voters {
+                    self.set_vote_flagged(voter, true).await?;
+                }
+
+                println!("flagged {} users", ring.voters.len());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for VotingRing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let voters = self.voters.iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(
+            f,
+            "author {}: users {voters} voted identically up to {} times",
+            self.author,
+            self.shared_votes,
+        )
+    }
+}
+
+pub fn spawn_vote_review(state: Arc<State>) {
+    let period = Duration::from_secs(state.config.voting.review_minutes.max(1) * 60);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = state.review_votes().await {
+                eprintln!("vote review failed: {e:#?}");
+            }
+        }
+    });
+}
@@ -5,7 +5,7 @@ use warp::{Filter, Rejection, Reply};
 use warp::body::BodyDeserializeError;
 use warp::filters::BoxedFilter;
 use warp::http::StatusCode;
-use warp::reject::{MethodNotAllowed, Reject};
+use warp::reject::{InvalidQuery, MethodNotAllowed, Reject};
 
 use crate::State;
 
@@ -15,29 +15,69 @@ mod write;
 mod erase;
 mod get_location;
 mod vote;
+mod withdraw_vote;
 mod get_mine;
 mod get_message;
 mod claim;
 mod ping;
 mod packs;
+mod glyphs;
+mod rotate;
+mod recover;
+mod create_link;
+mod redeem_link;
+mod get_tokens;
+mod revoke_token;
+mod export;
+mod get_account;
+mod get_appraisals;
+mod get_events;
+mod get_top;
+mod get_stats;
+mod get_grid;
+
+pub use get_grid::{GridBucket, GridKey};
+pub use get_stats::TerritoryStats;
+pub use get_top::TopKey;
 
 pub fn routes(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
     register::register(Arc::clone(&state))
+        .or(rotate::rotate(Arc::clone(&state)))
+        .or(recover::recover(Arc::clone(&state)))
+        .or(create_link::create_link(Arc::clone(&state)))
+        .or(redeem_link::redeem_link(Arc::clone(&state)))
+        .or(get_tokens::get_tokens(Arc::clone(&state)))
+        .or(revoke_token::revoke_token(Arc::clone(&state)))
+        .or(export::export(Arc::clone(&state)))
+        .or(get_account::get_account(Arc::clone(&state)))
+        .or(get_appraisals::get_appraisals(Arc::clone(&state)))
         .or(unregister::unregister(Arc::clone(&state)))
         .or(write::write(Arc::clone(&state)))
         .or(erase::erase(Arc::clone(&state)))
         .or(vote::vote(Arc::clone(&state)))
+        .or(withdraw_vote::withdraw_vote(Arc::clone(&state)))
+        .or(get_top::get_top(Arc::clone(&state)))
         .or(get_message::get_message(Arc::clone(&state)))
         .or(get_location::get_location(Arc::clone(&state)))
+        .or(get_events::get_events(Arc::clone(&state)))
         .or(get_mine::get_mine(Arc::clone(&state)))
         .or(claim::claim(Arc::clone(&state)))
         .or(ping::ping(Arc::clone(&state)))
         .or(packs::packs(Arc::clone(&state)))
+        .or(glyphs::glyphs(Arc::clone(&state)))
+        .or(get_stats::get_stats(Arc::clone(&state)))
+        .or(get_grid::get_grid(Arc::clone(&state)))
         .recover(handle_rejection)
         .boxed()
 }
 
 pub fn get_id(state: Arc<State>) -> BoxedFilter<((i64, i64), )> {
+    get_token(state)
+        .map(|(id, extra, _)| (id, extra))
+        .boxed()
+}
+
+pub fn get_token(state: Arc<State>) -> BoxedFilter<((i64, i64, i64), )> {
     warp::header::optional("x-api-key")
         .and_then(move |access_token: Option<String>| {
             let state = Arc::clone(&state);
@@ -49,13 +89,13 @@ pub fn get_id(state: Arc<State>) -> BoxedFilter<((i64, i64), )> {
                 let hashed = crate::util::hash(&access_token);
                 let id = sqlx::query!(
                     // language=sqlite
-                    "select id, extra from users where auth = ?",
+                    "select u.id, u.extra, t.id as token from auth_tokens t inner join users u on t.user = u.id where t.hash = ?",
                     hashed,
                 )
                     .fetch_optional(&state.db)
                     .await;
                 match id {
-                    Ok(Some(i)) => Ok((i.id, i.extra)),
+                    Ok(Some(i)) => Ok((i.id, i.extra, i.token)),
                     Ok(None) => Err(warp::reject::custom(WebError::InvalidAuthToken)),
                     Err(e) => Err(warp::reject::custom(AnyhowRejection(e.into()))),
                 }
@@ -64,6 +104,32 @@ pub fn get_id(state: Arc<State>) -> BoxedFilter<((i64, i64), )> {
         .boxed()
 }
 
+/// Resolves the optional `world` query parameter to the worlds whose messages
+/// are shared with it. Only worlds in the world list are accepted, since the
+/// scope is part of cache keys.
+pub fn get_scope(state: Arc<State>) -> BoxedFilter<(Option<Vec<u32>>, )> {
+    #[derive(serde::Deserialize)]
+    struct WorldQuery {
+        #[serde(default)]
+        world: Option<u32>,
+    }
+
+    warp::query::<WorldQuery>()
+        .and_then(move |query: WorldQuery| {
+            let state = Arc::clone(&state);
+            async move {
+                if let Some(world) = query.world {
+                    if !state.is_known_world(world).await {
+                        return Err(warp::reject::custom(WebError::InvalidWorld));
+                    }
+                }
+
+                Ok(state.world_scope(query.world).await)
+            }
+        })
+        .boxed()
+}
+
 #[derive(Debug)]
 pub enum WebError {
     MissingAuthToken,
@@ -75,6 +141,25 @@ pub enum WebError {
     InvalidExtraCode,
     MissingHousingInfo,
     UnnecessaryHousingInfo,
+    InvalidRecoveryCode,
+    InvalidLinkCode,
+    InvalidTokenLabel,
+    NoSuchToken,
+    CannotRevokeLastToken,
+    CannotVoteOwnMessage,
+    InvalidCursor,
+    InvalidProximity,
+    TerritoryNotWritable,
+    InvalidHousingInfo(&'static str),
+    MissingWorld,
+    InvalidPosition,
+    TooCloseToOwnMessage,
+    InvalidEmote,
+    EmoteNotAllowed,
+    InvalidGlyph,
+    InvalidTerritory,
+    InvalidWorld,
+    GlyphNotEntitled,
 }
 
 impl Reject for WebError {}
@@ -96,13 +181,32 @@ async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
             WebError::InvalidExtraCode => (StatusCode::BAD_REQUEST, "invalid_extra_code", "that extra code was not found".into()),
             WebError::MissingHousingInfo => (StatusCode::BAD_REQUEST, "missing_housing_info", "housing info was not provided - try updating the plugin".into()),
             WebError::UnnecessaryHousingInfo => (StatusCode::BAD_REQUEST, "unnecessary_housing_info", "a ward/plot was provided but not necessary - try updating the plugin".into()),
+            WebError::InvalidRecoveryCode => (StatusCode::BAD_REQUEST, "invalid_recovery_code", "that recovery code was not valid".into()),
+            WebError::InvalidLinkCode => (StatusCode::BAD_REQUEST, "invalid_link_code", "that link code was not valid or has expired".into()),
+            WebError::InvalidTokenLabel => (StatusCode::BAD_REQUEST, "invalid_token_label", "token labels must be between 1 and 64 characters".into()),
+            WebError::NoSuchToken => (StatusCode::NOT_FOUND, "no_such_token", "no token with that id was found".into()),
+            WebError::CannotRevokeLastToken => (StatusCode::BAD_REQUEST, "cannot_revoke_last_token", "you cannot revoke your only token - create a link code for another device first".into()),
+            WebError::CannotVoteOwnMessage => (StatusCode::BAD_REQUEST, "cannot_vote_own_message", "you cannot vote on your own message".into()),
+            WebError::InvalidCursor => (StatusCode::BAD_REQUEST, "invalid_cursor", "the cursor is not valid - start from the first page".into()),
+            WebError::InvalidProximity => (StatusCode::BAD_REQUEST, "invalid_proximity", "x, z and a positive radius must all be provided together".into()),
+            WebError::InvalidHousingInfo(field) => (StatusCode::BAD_REQUEST, "invalid_housing_info", format!("the {field} was not valid for this area")),
+            WebError::MissingWorld => (StatusCode::BAD_REQUEST, "missing_world", "a world was not provided - try updating the plugin".into()),
+            WebError::InvalidPosition => (StatusCode::BAD_REQUEST, "invalid_position", "the position was not valid for this area".into()),
+            WebError::TooCloseToOwnMessage => (StatusCode::BAD_REQUEST, "too_close_to_own_message", "you already have a message here - move away a little and try again".into()),
+            WebError::InvalidEmote => (StatusCode::BAD_REQUEST, "invalid_emote", "the emote data was malformed - try updating the plugin".into()),
+            WebError::EmoteNotAllowed => (StatusCode::BAD_REQUEST, "emote_not_allowed", "that emote cannot be attached to messages".into()),
+            WebError::InvalidGlyph => (StatusCode::BAD_REQUEST, "invalid_glyph", "the server does not have a glyph with that id".into()),
+            WebError::InvalidTerritory => (StatusCode::NOT_FOUND, "invalid_territory", "the server does not have any messages in that territory".into()),
+            WebError::InvalidWorld => (StatusCode::NOT_FOUND, "invalid_world", "the server does not have a world with that id".into()),
+            WebError::GlyphNotEntitled => (StatusCode::FORBIDDEN, "glyph_not_entitled", "you have not unlocked that glyph".into()),
+            WebError::TerritoryNotWritable => (StatusCode::BAD_REQUEST, "territory_not_writable", "messages cannot be written in this area".into()),
         }
     } else if err.is_not_found() {
         (StatusCode::NOT_FOUND, "not_found", "route was unknown to the server".into())
     } else if let Some(e) = err.find::<BodyDeserializeError>() {
         (StatusCode::BAD_REQUEST, "invalid_body", format!("invalid body: {e}"))
-    } else if err.find::<MethodNotAllowed>().is_some() {
-        (StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed", "that http method is not allowed on that route".into())
+    } else if let Some(e) = err.find::<InvalidQuery>() {
+        (StatusCode::BAD_REQUEST, "invalid_query", format!("invalid query: {e}"))
     } else if let Some(AnyhowRejection(e)) = err.find::<AnyhowRejection>() {
         eprintln!("{e:#?}");
         (
@@ -110,6 +214,8 @@ async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
             "internal_error",
             "an internal logic error occured".into(),
         )
+    } else if err.find::<MethodNotAllowed>().is_some() {
+        (StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed", "that http method is not allowed on that route".into())
     } else {
         eprintln!("{err:#?}");
         (
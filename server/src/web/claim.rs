@@ -33,7 +33,7 @@ async fn logic(state: Arc<State>, id: i64, bytes: Bytes) -> Result<impl Reply, R
         .map_err(warp::reject::custom)?;
     let rec = sqlx::query!(
         // language="sqlite"
-        r#"update extra_tokens set uses = case uses when -1 then -1 else max(0, uses - 1) end where id = ? returning extra as "extra!: i64""#,
+        r#"update extra_tokens set uses = case uses when -1 then -1 else max(0, uses - 1) end where id = ? returning extra as "extra!: i64", entitlement"#,
         code,
     )
         .fetch_optional(&mut *t)
@@ -42,6 +42,11 @@ async fn logic(state: Arc<State>, id: i64, bytes: Bytes) -> Result<impl Reply, R
         .map_err(AnyhowRejection)
         .map_err(warp::reject::custom)?;
 
+    let rec = match rec {
+        Some(rec) => rec,
+        None => return Err(warp::reject::custom(WebError::InvalidExtraCode)),
+    };
+
     let count = sqlx::query_scalar!(
         // language=sqlite
         "select count(*) as count from used_codes where id = ? and user = ?",
@@ -70,6 +75,20 @@ async fn logic(state: Arc<State>, id: i64, bytes: Bytes) -> Result<impl Reply, R
         .map_err(AnyhowRejection)
         .map_err(warp::reject::custom)?;
 
+    if let Some(entitlement) = &rec.entitlement {
+        sqlx::query!(
+            // language=sqlite
+            "insert or ignore into user_entitlements (user, entitlement) values (?, ?)",
+            id,
+            entitlement,
+        )
+            .execute(&mut *t)
+            .await
+            .context("could not grant entitlement")
+            .map_err(AnyhowRejection)
+            .map_err(warp::reject::custom)?;
+    }
+
     sqlx::query!(
         // language=sqlite
         "delete from extra_tokens where id = ? and uses = 0",
@@ -87,21 +106,17 @@ async fn logic(state: Arc<State>, id: i64, bytes: Bytes) -> Result<impl Reply, R
         .map_err(AnyhowRejection)
         .map_err(warp::reject::custom)?;
 
-    if let Some(code) = rec {
-        let result = sqlx::query!(
-            // language=sqlite
-            r#"update users set extra = extra + ? where id = ? returning extra as "extra!: i64""#,
-            code.extra,
-            id,
-        )
-            .fetch_one(&state.db)
-            .await
-            .context("could not update user")
-            .map_err(AnyhowRejection)
-            .map_err(warp::reject::custom)?;
-
-        return Ok(result.extra.to_string());
-    }
+    let result = sqlx::query!(
+        // language=sqlite
+        r#"update users set extra = extra + ? where id = ? returning extra as "extra!: i64""#,
+        rec.extra,
+        id,
+    )
+        .fetch_one(&state.db)
+        .await
+        .context("could not update user")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
 
-    Err(warp::reject::custom(WebError::InvalidExtraCode))
+    Ok(result.extra.to_string())
 }
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::State;
+use crate::web::AnyhowRejection;
+
+pub fn create_link(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::post()
+        .and(warp::path("account"))
+        .and(warp::path("links"))
+        .and(warp::path::end())
+        .and(super::get_id(Arc::clone(&state)))
+        .and_then(move |(id, _)| logic(Arc::clone(&state), id))
+        .boxed()
+}
+
+async fn logic(state: Arc<State>, id: i64) -> Result<impl Reply, Rejection> {
+    let code = crate::util::generate_token();
+    let hashed = crate::util::hash(&code);
+
+    sqlx::query!(
+        // language=sqlite
+        "delete from link_codes where expires <= current_timestamp",
+    )
+        .execute(&state.db)
+        .await
+        .context("could not delete expired link codes")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    // link codes are short-lived and can only be redeemed once
+    sqlx::query!(
+        // language=sqlite
+        "insert into link_codes (hash, user, expires) values (?, ?, datetime(current_timestamp, '+10 minutes'))",
+        hashed,
+        id,
+    )
+        .execute(&state.db)
+        .await
+        .context("could not insert link code into database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    Ok(code)
+}
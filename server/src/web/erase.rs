@@ -5,6 +5,7 @@ use uuid::Uuid;
 use warp::{Filter, Rejection, Reply};
 use warp::filters::BoxedFilter;
 
+use crate::events::{EventKind, EventLocation};
 use crate::State;
 use crate::web::AnyhowRejection;
 
@@ -20,16 +21,36 @@ pub fn erase(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
 
 async fn logic(state: Arc<State>, id: i64, post_id: Uuid) -> Result<impl Reply, Rejection> {
     let post_id = post_id.simple().to_string();
-    sqlx::query!(
+    let erased = sqlx::query!(
         // language=sqlite
-        "delete from messages where id = ? and user = ?",
+        "delete from messages where id = ? and user = ? returning territory, world, ward, plot",
         post_id,
         id,
     )
-        .execute(&state.db)
+        .fetch_optional(&state.db)
         .await
         .context("could not delete message from database")
         .map_err(AnyhowRejection)
         .map_err(warp::reject::custom)?;
+
+    if let Some(erased) = erased {
+        if let Err(e) = state.update_vote_weights(id).await {
+            eprintln!("could not update vote weights: {e:#?}");
+        }
+
+        let location = EventLocation {
+            territory: erased.territory,
+            world: erased.world,
+            ward: erased.ward,
+            plot: erased.plot,
+        };
+        let removed = EventKind::MessageRemoved {
+            id: post_id,
+        };
+        if let Err(e) = state.publish_from(id, location, removed).await {
+            eprintln!("could not publish erased message: {e:#?}");
+        }
+    }
+
     Ok(warp::reply())
 }
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::emote::{self, EmoteData};
+use crate::message::ExportedMessage;
+use crate::State;
+use crate::web::AnyhowRejection;
+
+pub fn export(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::get()
+        .and(warp::path("account"))
+        .and(warp::path("export"))
+        .and(warp::path::end())
+        .and(super::get_id(Arc::clone(&state)))
+        .and_then(move |(id, _)| logic(Arc::clone(&state), id))
+        .boxed()
+}
+
+#[derive(Serialize)]
+pub struct Export {
+    exported: NaiveDateTime,
+    user: ExportedUser,
+    tokens: Vec<ExportedToken>,
+    messages: Vec<ExportedMessage>,
+    votes: Vec<ExportedVote>,
+    used_codes: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ExportedUser {
+    id: i64,
+    extra: i64,
+    created: Option<NaiveDateTime>,
+    last_seen: NaiveDateTime,
+    has_recovery_code: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shadowbanned: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct ExportedToken {
+    id: i64,
+    label: String,
+    created: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct ExportedVote {
+    message: String,
+    vote: i64,
+    created: Option<NaiveDateTime>,
+}
+
+async fn logic(state: Arc<State>, id: i64) -> Result<impl Reply, Rejection> {
+    let mut t = state.db.begin()
+        .await
+        .context("could not start transaction")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    let mut user = sqlx::query_as!(
+        ExportedUser,
+        // language=sqlite
+        r#"
+            select id,
+                   extra,
+                   created,
+                   last_seen,
+                   recovery is not null as "has_recovery_code!: bool",
+                   shadowbanned as "shadowbanned?: bool"
+            from users
+            where id = ?"#,
+        id,
+    )
+        .fetch_one(&mut *t)
+        .await
+        .context("could not get user from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    if !state.config.expose_shadowban {
+        user.shadowbanned = None;
+    }
+
+    let tokens = sqlx::query_as!(
+        ExportedToken,
+        // language=sqlite
+        "select id, label, created from auth_tokens where user = ? order by created",
+        id,
+    )
+        .fetch_all(&mut *t)
+        .await
+        .context("could not get auth tokens from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    let mut messages = sqlx::query_as!(
+        ExportedMessage,
+        // language=sqlite
+        r#"
+            select m.id,
+                   m.territory,
+                   m.world,
+                   m.ward,
+                   m.plot,
+                   m.x,
+                   m.y,
+                   m.z,
+                   m.yaw,
+                   m.message,
+                   coalesce(s.positive_votes, 0) as "positive_votes!: i32",
+                   coalesce(s.negative_votes, 0) as "negative_votes!: i32",
+                   m.glyph,
+                   m.emote as "emote: EmoteData",
+                   m.created
+            from messages m
+                     left join message_scores s on m.id = s.message
+            where m.user = ?
+            order by m.created"#,
+        id,
+    )
+        .fetch_all(&mut *t)
+        .await
+        .context("could not get messages from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    for msg in &mut messages {
+        emote::scrub_stored(&mut msg.emote, state.config.emotes.appearance);
+    }
+
+    let votes = sqlx::query_as!(
+        ExportedVote,
+        // language=sqlite
+        "select message, vote, created from votes where user = ? order by created",
+        id,
+    )
+        .fetch_all(&mut *t)
+        .await
+        .context("could not get votes from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    let used_codes = sqlx::query_scalar!(
+        // language=sqlite
+        "select id from used_codes where user = ?",
+        id,
+    )
+        .fetch_all(&mut *t)
+        .await
+        .context("could not get used codes from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    t.commit()
+        .await
+        .context("could not commit transaction")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    let export = Export {
+        exported: Utc::now().naive_utc(),
+        user,
+        tokens,
+        messages,
+        votes,
+        used_codes,
+    };
+
+    Ok(warp::reply::json(&export))
+}
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::State;
+use crate::web::AnyhowRejection;
+
+pub fn get_account(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::get()
+        .and(warp::path("account"))
+        .and(warp::path::end())
+        .and(super::get_id(Arc::clone(&state)))
+        .and_then(move |(id, extra)| logic(Arc::clone(&state), id, extra))
+        .boxed()
+}
+
+#[derive(Serialize)]
+pub struct Account {
+    messages: i64,
+    limit: i64,
+    remaining: i64,
+    positive_votes: i64,
+    negative_votes: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shadowbanned: Option<bool>,
+    created: Option<NaiveDateTime>,
+    last_seen: NaiveDateTime,
+    entitlements: Vec<String>,
+}
+
+async fn logic(state: Arc<State>, id: i64, extra: i64) -> Result<impl Reply, Rejection> {
+    let user = sqlx::query!(
+        // language=sqlite
+        r#"
+            select u.created,
+                   u.last_seen,
+                   u.shadowbanned as "shadowbanned: bool",
+                   (select count(*) from messages m where m.user = u.id) as "messages!: i64",
+                   (select coalesce(sum(s.positive_votes), 0)
+                    from message_scores s
+                             inner join messages m on s.message = m.id
+                    where m.user = u.id) as "positive_votes!: i64",
+                   (select coalesce(sum(s.negative_votes), 0)
+                    from message_scores s
+                             inner join messages m on s.message = m.id
+                    where m.user = u.id) as "negative_votes!: i64"
+            from users u
+            where u.id = ?"#,
+        id,
+    )
+        .fetch_one(&state.db)
+        .await
+        .context("could not get user from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    let entitlements = sqlx::query_scalar!(
+        // language=sqlite
+        "select entitlement from user_entitlements where user = ? order by entitlement",
+        id,
+    )
+        .fetch_all(&state.db)
+        .await
+        .context("could not get entitlements from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    let limit = state.config.max_messages as i64 + extra;
+    let account = Account {
+        messages: user.messages,
+        limit,
+        remaining: (limit - user.messages).max(0),
+        positive_votes: user.positive_votes,
+        negative_votes: user.negative_votes,
+        shadowbanned: state.config.expose_shadowban.then_some(user.shadowbanned),
+        created: user.created,
+        last_seen: user.last_seen,
+        entitlements,
+    };
+
+    Ok(warp::reply::json(&account))
+}
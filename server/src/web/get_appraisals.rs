@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::{State, util};
+use crate::web::{AnyhowRejection, WebError};
+
+pub fn get_appraisals(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::get()
+        .and(warp::path("account"))
+        .and(warp::path("appraisals"))
+        .and(warp::path::end())
+        .and(super::get_id(Arc::clone(&state)))
+        .and(warp::query::<GetAppraisalsQuery>())
+        .and_then(move |(id, _), query| logic(Arc::clone(&state), id, query))
+        .boxed()
+}
+
+#[derive(Deserialize)]
+pub struct GetAppraisalsQuery {
+    #[serde(default)]
+    since: Option<NaiveDateTime>,
+    /// the `next` cursor from the previous page
+    #[serde(default)]
+    cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Appraisal {
+    message_id: String,
+    message: String,
+    vote: i64,
+    created: NaiveDateTime,
+    #[serde(skip)]
+    rowid: i64,
+}
+
+async fn logic(state: Arc<State>, id: i64, query: GetAppraisalsQuery) -> Result<impl Reply, Rejection> {
+    // votes are paged by (created, rowid) so that votes sharing a timestamp
+    // are not skipped at the edge of a page
+    let (since, after) = match &query.cursor {
+        Some(cursor) => util::decode_cursor::<(NaiveDateTime, i64)>(cursor)
+            .ok_or(WebError::InvalidCursor)
+            .map_err(warp::reject::custom)?,
+        None => (query.since.unwrap_or_default(), i64::MAX),
+    };
+
+    let appraisals = sqlx::query_as!(
+        Appraisal,
+        // language=sqlite
+        r#"
+            select v.message  as "message_id!: String",
+                   m.message,
+                   v.vote,
+                   v.created  as "created!: NaiveDateTime",
+                   v.rowid    as "rowid!: i64"
+            from votes v
+                     inner join messages m on v.message = m.id
+            where m.user = ?
+              and (v.created, v.rowid) > (strftime('%Y-%m-%d %H:%M:%f', ?), ?)
+              and v.vote != 0
+              and not v.nullified
+            order by v.created, v.rowid
+            limit 100"#,
+        id,
+        since,
+        after,
+    )
+        .fetch_all(&state.db)
+        .await
+        .context("could not get appraisals from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    #[derive(Serialize)]
+    struct Appraisals {
+        appraisals: Vec<Appraisal>,
+        next: String,
+    }
+
+    let next = appraisals.last()
+        .map(|appraisal| (appraisal.created, appraisal.rowid))
+        .unwrap_or((since, after));
+
+    Ok(warp::reply::json(&Appraisals {
+        appraisals,
+        next: util::encode_cursor(&next),
+    }))
+}
@@ -0,0 +1,99 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::BroadcastStream;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+use warp::sse::Event;
+
+use crate::events::{EventKind, EventLocation};
+use crate::State;
+
+pub fn get_events(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::get()
+        .and(warp::path("messages"))
+        .and(warp::path::param())
+        .and(warp::path("events"))
+        .and(warp::path::end())
+        .and(super::get_id(Arc::clone(&state)))
+        .and(warp::query::<GetEventsQuery>())
+        .and_then(move |location: u32, (id, _), query| logic(Arc::clone(&state), id, location, query))
+        .boxed()
+}
+
+#[derive(Deserialize)]
+pub struct GetEventsQuery {
+    #[serde(default)]
+    world: Option<u32>,
+    #[serde(default)]
+    ward: Option<u32>,
+    #[serde(default)]
+    plot: Option<u32>,
+}
+
+async fn logic(state: Arc<State>, id: i64, location: u32, query: GetEventsQuery) -> Result<impl Reply, Rejection> {
+    let territory = state.territory(location).await;
+    let housing = territory.is_housing();
+    territory.validate_location(query.world, query.ward, query.plot)
+        .map_err(warp::reject::custom)?;
+
+    let worlds = if housing {
+        None
+    } else {
+        state.world_scope(query.world).await
+    };
+
+    let subscribed = EventLocation {
+        territory: location as i64,
+        world: query.world.map(Into::into),
+        ward: query.ward.map(Into::into),
+        plot: query.plot.map(Into::into),
+    };
+
+    let stream = BroadcastStream::new(state.events.subscribe())
+        .filter_map(move |event| {
+            let event = match event {
+                Ok(event) => event,
+                // the client missed some events, so it should fetch the territory again
+                Err(_) => return Some(Event::default().event("resync").data("")),
+            };
+
+            if !event.location.matches(&subscribed, housing) {
+                return None;
+            }
+
+            if let Some(worlds) = &worlds {
+                if !event.location.world.is_some_and(|world| worlds.contains(&(world as u32))) {
+                    return None;
+                }
+            }
+
+            if !event.public && event.author != id {
+                return None;
+            }
+
+            // the same rule get_location uses. a message voted below the
+            // threshold is removed, and one voted back above it shows up on
+            // the next fetch
+            if event.hidden && event.author != id {
+                let EventKind::VotesChanged { id, .. } = &event.kind else {
+                    return None;
+                };
+
+                return Event::default()
+                    .event("message")
+                    .json_data(&EventKind::MessageRemoved { id: id.clone() })
+                    .ok();
+            }
+
+            Event::default()
+                .event("message")
+                .json_data(&event.kind)
+                .ok()
+        })
+        .map(Ok::<_, Infallible>);
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
+}
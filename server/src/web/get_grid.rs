@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::State;
+use crate::web::{AnyhowRejection, WebError};
+
+pub fn get_grid(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::get()
+        .and(warp::path("stats"))
+        .and(warp::path("territories"))
+        .and(warp::path::param())
+        .and(warp::path("grid"))
+        .and(warp::path::end())
+        .and(warp::query::<GetGridQuery>())
+        .and(super::get_scope(Arc::clone(&state)))
+        .and_then(move |territory: u32, query, scope| logic(Arc::clone(&state), territory, query, scope))
+        .boxed()
+}
+
+const MIN_SIZE: u32 = 50;
+const MAX_SIZE: u32 = 500;
+/// buckets with fewer messages than this are left out, since a bucket with a
+/// single message in it gives that message's location away
+const MIN_BUCKET_COUNT: u32 = 3;
+
+/// A territory, bucket size and world scope.
+pub type GridKey = (u32, u32, Option<Vec<u32>>);
+
+#[derive(Deserialize)]
+pub struct GetGridQuery {
+    #[serde(default = "size_default")]
+    size: u32,
+}
+
+fn size_default() -> u32 {
+    50
+}
+
+#[derive(Serialize)]
+pub struct GridBucket {
+    // the lowest x and z coordinates covered by this bucket
+    x: f64,
+    z: f64,
+    count: u32,
+}
+
+async fn logic(state: Arc<State>, territory: u32, query: GetGridQuery, scope: Option<Vec<u32>>) -> Result<impl Reply, Rejection> {
+    // keep buckets coarse so this can't be used to locate individual messages.
+    // sizes are also rounded to steps of the minimum to limit cache entries
+    let size = query.size.clamp(MIN_SIZE, MAX_SIZE) / MIN_SIZE * MIN_SIZE;
+    let known = state.is_known_territory(territory)
+        .await
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+    if !known {
+        return Err(warp::reject::custom(WebError::InvalidTerritory));
+    }
+    let key = (territory, size, scope);
+    if let Some(grid) = state.grid_cache.get(&key) {
+        return Ok(warp::reply::json(&*grid));
+    }
+
+    let worlds = key.2.as_ref().map(|worlds| serde_json::json!(worlds).to_string());
+
+    let territory_id = territory as i64;
+    let positions = sqlx::query!(
+        // language=sqlite
+        r#"
+            select m.x,
+                   m.z
+            from messages m
+                     inner join users u on m.user = u.id
+            where m.territory = ?
+              and not u.shadowbanned
+              and (? is null or m.world in (select value from json_each(?)))"#,
+        territory_id,
+        worlds,
+        worlds,
+    )
+        .fetch_all(&state.db)
+        .await
+        .context("could not get message positions from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    let size_f = size as f64;
+    let mut counts: HashMap<(i64, i64), u32> = HashMap::new();
+    for position in positions {
+        let bucket = (
+            (position.x / size_f).floor() as i64,
+            (position.z / size_f).floor() as i64,
+        );
+        *counts.entry(bucket).or_default() += 1;
+    }
+
+    let mut grid: Vec<GridBucket> = counts.into_iter()
+        .filter(|(_, count)| *count >= MIN_BUCKET_COUNT)
+        .map(|((x, z), count)| GridBucket {
+            x: x as f64 * size_f,
+            z: z as f64 * size_f,
+            count,
+        })
+        .collect();
+    grid.sort_unstable_by(|a, b| a.x.total_cmp(&b.x).then(a.z.total_cmp(&b.z)));
+
+    let grid = state.grid_cache.insert(key, grid);
+    Ok(warp::reply::json(&*grid))
+}
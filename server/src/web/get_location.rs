@@ -1,19 +1,21 @@
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
-use anyhow::Context;
-use chrono::{Duration, Utc};
-use parking_lot::RwLock;
-use rand::Rng;
+use chrono::{Duration, NaiveDateTime, Utc};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
 use rayon::prelude::*;
-use serde::Deserialize;
-use sqlx::types::Json;
+use serde::{Deserialize, Serialize};
 use warp::{Filter, Rejection, Reply};
 use warp::filters::BoxedFilter;
 
-use crate::message::{EmoteData, RetrievedMessage};
+use crate::emote;
+use crate::message::RetrievedMessage;
+use crate::query::MessageQuery;
 use crate::State;
-use crate::util::HOUSING_ZONES;
 use crate::web::{AnyhowRejection, WebError};
 
 pub fn get_location(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
@@ -35,186 +37,213 @@ pub struct GetLocationQuery {
     ward: Option<u32>,
     #[serde(default)]
     plot: Option<u32>,
+    // comma-separated ids of the messages the client is currently showing
+    #[serde(default)]
+    known: Option<String>,
+    #[serde(default)]
+    since: Option<NaiveDateTime>,
+    #[serde(default)]
+    x: Option<f32>,
+    #[serde(default)]
+    z: Option<f32>,
+    #[serde(default)]
+    radius: Option<f32>,
+}
+
+#[derive(Serialize)]
+pub struct Delta {
+    added: Vec<RetrievedMessage>,
+    removed: Vec<String>,
+    updated: Vec<VoteUpdate>,
+    synced: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct VoteUpdate {
+    id: String,
+    positive_votes: i32,
+    negative_votes: i32,
+    user_vote: i64,
 }
 
 async fn logic(state: Arc<State>, id: i64, location: u32, query: GetLocationQuery) -> Result<impl Reply, Rejection> {
-    let housing = HOUSING_ZONES.contains(&location);
-    if housing && (query.world.is_none() || query.ward.is_none()) {
-        return Err(warp::reject::custom(WebError::MissingHousingInfo));
+    let territory = state.territory(location).await;
+    let housing = territory.is_housing();
+    territory.validate_location(query.world, query.ward, query.plot)
+        .map_err(warp::reject::custom)?;
+
+    let proximity = match (query.x, query.z, query.radius) {
+        (Some(x), Some(z), Some(radius)) if x.is_finite() && z.is_finite() && radius > 0.0 => {
+            Some((x as f64, z as f64, radius.min(state.config.max_radius) as f64))
+        }
+        (None, None, None) => None,
+        _ => return Err(warp::reject::custom(WebError::InvalidProximity)),
+    };
+
+    let mut messages_query = MessageQuery::new(id)
+        .territory(location as i64)
+        .visible();
+
+    if housing {
+        messages_query = messages_query.housing(query.world, query.ward, query.plot);
+    } else if let Some(worlds) = state.world_scope(query.world).await {
+        messages_query = messages_query.worlds(worlds);
     }
 
-    if !housing && (query.ward.is_some() || query.plot.is_some()) {
-        return Err(warp::reject::custom(WebError::UnnecessaryHousingInfo));
+    // the bounding box is only an approximation, so the exact distance is
+    // checked once the messages are loaded
+    if let Some((x, z, radius)) = proximity {
+        messages_query = messages_query.bounds(x - radius, x + radius, z - radius, z + radius);
     }
 
-    let world = if housing {
-        query.world
-    } else {
-        None
-    };
+    // taken before the query so that anything committed while it runs is
+    // sent again by the next delta instead of being missed
+    let synced = Utc::now().naive_utc();
+    let mut messages: Vec<RetrievedMessage> = messages_query.fetch_all(&state.db)
+        .await
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
 
-    let location = location as i64;
-    let mut messages = if housing {
-        sqlx::query_as!(
-            RetrievedMessage,
-            // language=sqlite
-            r#"
-                select m.id,
-                       m.x,
-                       m.y,
-                       m.z,
-                       m.yaw,
-                       m.message,
-                       coalesce(sum(v.vote between 0 and 1), 0)  as positive_votes,
-                       coalesce(sum(v.vote between -1 and 0), 0) as negative_votes,
-                       coalesce(sum(case when v.user = ?1 then v.vote else 0 end), 0) as user_vote,
-                       m.glyph,
-                       m.emote as "emote: Json<Option<EmoteData>>",
-                       m.created,
-                       m.user,
-                       coalesce(cast((julianday(current_timestamp) - julianday(u.last_seen)) * 1440 as int), 0) as last_seen_minutes
-                from messages m
-                         left join votes v on m.id = v.message
-                         inner join users u on m.user = u.id
-                where m.territory = ?2 and (m.user = ?1 or not u.shadowbanned) and m.world is ?3 and m.ward is ?4 and m.plot is ?5
-                group by m.id
-            "#,
-            id,
-            location,
-            world,
-            query.ward,
-            query.plot,
-        )
-            .fetch_all(&state.db)
-            .await
-            .context("could not get messages from database")
-            .map_err(AnyhowRejection)
-            .map_err(warp::reject::custom)?
-    } else {
-        sqlx::query_as!(
-            RetrievedMessage,
-            // language=sqlite
-            r#"
-                select m.id,
-                       m.x,
-                       m.y,
-                       m.z,
-                       m.yaw,
-                       m.message,
-                       coalesce(sum(v.vote between 0 and 1), 0)  as positive_votes,
-                       coalesce(sum(v.vote between -1 and 0), 0) as negative_votes,
-                       coalesce(sum(case when v.user = ?1 then v.vote else 0 end), 0) as user_vote,
-                       m.glyph,
-                       m.emote as "emote: Json<Option<EmoteData>>",
-                       m.created,
-                       m.user,
-                       coalesce(cast((julianday(current_timestamp) - julianday(u.last_seen)) * 1440 as int), 0) as last_seen_minutes
-                from messages m
-                         left join votes v on m.id = v.message
-                         inner join users u on m.user = u.id
-                where m.territory = ?2 and (m.id = ?1 or not u.shadowbanned)
-                group by m.id
-            "#,
-            id,
-            location,
-        )
-            .fetch_all(&state.db)
-            .await
-            .context("could not get messages from database")
-            .map_err(AnyhowRejection)
-            .map_err(warp::reject::custom)?
-    };
+    for msg in &mut messages {
+        emote::scrub_stored(&mut msg.emote, state.config.emotes.appearance);
+    }
 
-    tokio::task::block_in_place(|| filter_messages(&mut messages, id, state.config.vote_threshold_hide));
-    Ok(warp::reply::json(&messages))
+    if let Some((x, z, radius)) = proximity {
+        messages.retain(|msg| (msg.x - x).powi(2) + (msg.z - z).powi(2) <= radius.powi(2));
+    }
+
+    let known: HashSet<String> = query.known
+        .as_deref()
+        .map(|known| known.split(',').filter(|id| !id.is_empty()).map(ToOwned::to_owned).collect())
+        .unwrap_or_default();
+
+    tokio::task::block_in_place(|| filter_messages(&mut messages, id, state.config.vote_threshold_hide.into()));
+
+    if query.known.is_none() {
+        return Ok(warp::reply::json(&messages));
+    }
+
+    let removed = known.iter()
+        .filter(|known_id| !messages.iter().any(|msg| &msg.id == *known_id))
+        .cloned()
+        .collect();
+
+    let (kept, added): (Vec<_>, Vec<_>) = messages.into_iter()
+        .partition(|msg| known.contains(&msg.id));
+
+    let updated = kept.into_iter()
+        .filter(|msg| match (query.since, msg.votes_updated) {
+            (Some(since), Some(votes_updated)) => votes_updated > since,
+            (Some(_), None) => false,
+            (None, _) => true,
+        })
+        .map(|msg| VoteUpdate {
+            id: msg.id,
+            positive_votes: msg.positive_votes,
+            negative_votes: msg.negative_votes,
+            user_vote: msg.user_vote,
+        })
+        .collect();
+
+    Ok(warp::reply::json(&Delta {
+        added,
+        removed,
+        updated,
+        synced,
+    }))
 }
 
-fn filter_messages(messages: &mut Vec<RetrievedMessage>, id: i64, vote_threshold_hide: i32) {
+fn filter_messages(messages: &mut Vec<RetrievedMessage>, id: i64, vote_threshold_hide: f64) {
     // remove messages where the user has been offline for over 35 minutes
     // also remove messages with low score (that aren't the from the user)
-    messages.retain(|msg| msg.last_seen_minutes < 35 && (msg.user == id || (msg.positive_votes - msg.negative_votes) >= vote_threshold_hide));
+    messages.retain(|msg| msg.last_seen_minutes < 35 && (msg.user == id || msg.score >= vote_threshold_hide));
 
     // shuffle messages since we'll be excluding later based on messages
-    // that have already been included, so this will be more fair
-    messages.shuffle(&mut rand::thread_rng());
+    // that have already been included, so this will be more fair. the
+    // shuffle and each message's roll are seeded by the viewer and the hour
+    // so that refreshing shows the same messages instead of flickering
+    let period = Utc::now().timestamp() / 3600;
+    messages.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+    messages.shuffle(&mut StdRng::seed_from_u64(seed(&(id, period))));
 
     // just count nearby messages. this is O(n^2) but alternatives are hard
-    let ids = RwLock::new(Vec::with_capacity(messages.len()));
-    messages.par_iter()
-        .for_each(|a| {
-            if a.user == id {
-                ids.write().push(a.id.clone());
-                return;
+    let nearby_messages: Vec<Vec<usize>> = messages.par_iter()
+        .map(|a| {
+            messages.iter()
+                .enumerate()
+                .filter(|(_, b)| a.id != b.id)
+                .filter(|(_, b)| {
+                    let distance = (a.x - b.x).powi(2)
+                        + (a.y - b.y).powi(2)
+                        + (a.z - b.z).powi(2);
+                    // 10 squared
+                    distance < 100.0
+                })
+                .map(|(i, _)| i)
+                .collect()
+        })
+        .collect();
+
+    // decided in order so that the same messages always give the same result
+    let mut visible = vec![false; messages.len()];
+    for (i, a) in messages.iter().enumerate() {
+        if a.user == id {
+            visible[i] = true;
+            continue;
+        }
+
+        let nearby_ids = &nearby_messages[i];
+        let mut nearby = nearby_ids.len() as u32;
+        let (numerator, denominator) = if nearby <= 2 {
+            // no need to do calculations for groups of three or fewer
+            (17, 20)
+        } else {
+            let already_visible = nearby_ids.iter()
+                .filter(|&&j| visible[j])
+                .count();
+
+            if already_visible >= 3 {
+                continue;
             }
 
-            let mut nearby_ids = Vec::new();
-            for b in messages.iter() {
-                if a.id == b.id {
-                    continue;
-                }
-
-                let distance = (a.x - b.x).powi(2)
-                    + (a.y - b.y).powi(2)
-                    + (a.z - b.z).powi(2);
-                // 10 squared
-                if distance >= 100.0 {
-                    continue;
-                }
-
-                nearby_ids.push(&b.id);
+            let time_since_creation = a.created.signed_duration_since(Utc::now().naive_utc());
+            let brand_new = time_since_creation < Duration::minutes(30);
+            let new = time_since_creation < Duration::hours(2);
+
+            let mut numerator = 1;
+            if brand_new {
+                numerator = nearby;
+            } else if new {
+                numerator += (nearby / 3).min(1);
             }
 
-            let mut nearby = nearby_ids.len() as u32;
-            let (numerator, denominator) = if nearby <= 2 {
-                // no need to do calculations for groups of three or fewer
-                (17, 20)
-            } else {
-                {
-                    // do this in its own scope to keep the read lock,
-                    // preventing a race where we can end up with four messages
-                    let ids = ids.read();
-                    let already_visible = nearby_ids.iter()
-                        .filter(|id| ids.contains(id))
-                        .count();
-
-                    if already_visible >= 3 {
-                        return;
-                    }
-                };
-
-                let time_since_creation = a.created.signed_duration_since(Utc::now().naive_utc());
-                let brand_new = time_since_creation < Duration::minutes(30);
-                let new = time_since_creation < Duration::hours(2);
-
-                let mut numerator = 1;
-                if brand_new {
-                    numerator = nearby;
-                } else if new {
-                    numerator += (nearby / 3).min(1);
-                }
-
-                let score = (a.positive_votes - a.negative_votes).max(0);
-                if score > 0 {
-                    let pad = score as f32 / nearby as f32;
-                    let rounded = pad.floor() as u32;
-                    numerator += rounded.max(nearby / 2);
-                }
-
-                nearby *= 2;
-
-                if numerator * 5 > nearby * 4 {
-                    numerator = 4;
-                    nearby = 5;
-                }
-
-                (numerator, nearby)
-            };
-
-            if rand::thread_rng().gen_ratio(numerator.min(denominator), denominator) {
-                ids.write().push(a.id.clone());
+            let score = a.score.max(0.0);
+            if score > 0.0 {
+                let pad = score as f32 / nearby as f32;
+                let rounded = pad.floor() as u32;
+                numerator += rounded.max(nearby / 2);
             }
-        });
 
-    let ids = ids.into_inner();
-    messages.retain(|msg| ids.contains(&msg.id));
+            nearby *= 2;
+
+            if numerator * 5 > nearby * 4 {
+                numerator = 4;
+                nearby = 5;
+            }
+
+            (numerator, nearby)
+        };
+
+        visible[i] = StdRng::seed_from_u64(seed(&(id, period, &a.id)))
+            .gen_ratio(numerator.min(denominator), denominator);
+    }
+
+    let mut visible = visible.into_iter();
+    messages.retain(|_| visible.next().unwrap_or(false));
+}
+
+fn seed(key: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
 }
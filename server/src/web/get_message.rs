@@ -1,12 +1,12 @@
 use std::sync::Arc;
 
-use anyhow::Context;
-use sqlx::types::Json;
 use uuid::Uuid;
 use warp::{Filter, Rejection, Reply};
 use warp::filters::BoxedFilter;
 
-use crate::message::{EmoteData, RetrievedMessageTerritory};
+use crate::emote;
+use crate::message::RetrievedMessageTerritory;
+use crate::query::MessageQuery;
 use crate::State;
 use crate::web::{AnyhowRejection, WebError};
 
@@ -22,39 +22,15 @@ pub fn get_message(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
 
 async fn logic(state: Arc<State>, id: i64, message_id: Uuid) -> Result<impl Reply, Rejection> {
     let message_id = message_id.simple().to_string();
-    let message = sqlx::query_as!(
-        RetrievedMessageTerritory,
-        // language=sqlite
-        r#"
-            select m.id,
-                   m.territory,
-                   m.world,
-                   m.ward,
-                   m.plot,
-                   m.x,
-                   m.y,
-                   m.z,
-                   m.yaw,
-                   m.message,
-                   coalesce(sum(v.vote between 0 and 1), 0)  as positive_votes,
-                   coalesce(sum(v.vote between -1 and 0), 0) as negative_votes,
-                   coalesce(sum(case when v.user = ? then v.vote else 0 end), 0) as user_vote,
-                   m.glyph,
-                   m.emote as "emote: Json<Option<EmoteData>>",
-                   m.created
-            from messages m
-                     left join votes v on m.id = v.message
-            where m.id = ?
-            group by m.id"#,
-        id,
-        message_id,
-    )
+    let message: Option<RetrievedMessageTerritory> = MessageQuery::new(id)
+        .id(message_id)
+        .visible()
         .fetch_optional(&state.db)
         .await
-        .context("could not get message from database")
         .map_err(AnyhowRejection)
         .map_err(warp::reject::custom)?;
 
-    let message = message.ok_or_else(|| warp::reject::custom(WebError::NoSuchMessage))?;
+    let mut message = message.ok_or_else(|| warp::reject::custom(WebError::NoSuchMessage))?;
+    emote::scrub_stored(&mut message.emote, state.config.emotes.appearance);
     Ok(warp::reply::json(&message))
 }
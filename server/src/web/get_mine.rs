@@ -1,73 +1,104 @@
-use std::collections::HashMap;
 use std::sync::Arc;
 
-use anyhow::Context;
-use sqlx::types::Json;
+use serde::Deserialize;
 use warp::{Filter, Rejection, Reply};
 use warp::filters::BoxedFilter;
 
-use crate::message::{EmoteData, OwnMessage};
-use crate::State;
-use crate::web::AnyhowRejection;
+use crate::emote;
+use crate::message::OwnMessage;
+use crate::query::{MessageCursor, MessageOrder, MessageQuery};
+use crate::{State, util};
+use crate::web::{AnyhowRejection, WebError};
 
 pub fn get_mine(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
     warp::get()
         .and(warp::path("messages"))
         .and(warp::path::end())
         .and(super::get_id(Arc::clone(&state)))
-        .and(warp::query())
-        .and_then(move |(id, extra), query: HashMap<String, String>| logic(Arc::clone(&state), id, extra, query))
+        .and(warp::query::<GetMineQuery>())
+        .and_then(move |(id, extra), query| logic(Arc::clone(&state), id, extra, query))
         .boxed()
 }
 
-async fn logic(state: Arc<State>, id: i64, extra: i64, mut query: HashMap<String, String>) -> Result<impl Reply, Rejection> {
-    let version = query.remove("v")
-        .unwrap_or_else(|| "1".to_string())
-        .parse::<u8>()
-        .unwrap_or(1);
-
-    let mut messages = sqlx::query_as!(
-        OwnMessage,
-        // language=sqlite
-        r#"
-            select m.id,
-                   m.territory,
-                   m.world,
-                   m.ward,
-                   m.plot,
-                   m.x,
-                   m.y,
-                   m.z,
-                   m.yaw,
-                   m.message,
-                   coalesce(sum(v.vote between 0 and 1), 0)  as positive_votes,
-                   coalesce(sum(v.vote between -1 and 0), 0) as negative_votes,
-                   coalesce(sum(case when v.user = ? then v.vote else 0 end), 0) as user_vote,
-                   m.glyph,
-                   m.created,
-                   m.emote as "emote: Json<Option<EmoteData>>",
-                   0 as "is_hidden: bool"
-            from messages m
-                     left join votes v on m.id = v.message
-            where m.user = ?
-            group by m.id"#,
-        id,
-        id,
-    )
-        .fetch_all(&state.db)
+#[derive(Deserialize)]
+pub struct GetMineQuery {
+    #[serde(default = "version_default")]
+    v: u8,
+    #[serde(default)]
+    sort: MessageOrder,
+    #[serde(default)]
+    territory: Option<u32>,
+    #[serde(default)]
+    hidden: Option<bool>,
+    // the `next` cursor from the previous page
+    #[serde(default)]
+    cursor: Option<String>,
+    #[serde(default)]
+    limit: Option<u32>,
+}
+
+fn version_default() -> u8 {
+    1
+}
+
+const MAX_LIMIT: u32 = 100;
+
+async fn logic(state: Arc<State>, id: i64, extra: i64, query: GetMineQuery) -> Result<impl Reply, Rejection> {
+    let threshold = state.config.vote_threshold_hide.into();
+    let mut messages_query = MessageQuery::new(id)
+        .author(id)
+        .order(query.sort);
+
+    if let Some(territory) = query.territory {
+        messages_query = messages_query.territory(territory as i64);
+    }
+
+    if let Some(hidden) = query.hidden {
+        messages_query = messages_query.hidden(threshold, hidden);
+    }
+
+    if let Some(cursor) = &query.cursor {
+        let cursor: MessageCursor = util::decode_cursor(cursor)
+            .ok_or(WebError::InvalidCursor)
+            .map_err(warp::reject::custom)?;
+        // the keys of one order mean nothing in another
+        if cursor.order != query.sort {
+            return Err(warp::reject::custom(WebError::InvalidCursor));
+        }
+        messages_query = messages_query.after(cursor);
+    }
+
+    // fetch one more than asked for to know if there is another page
+    let limit = query.limit.map(|limit| limit.clamp(1, MAX_LIMIT));
+    if let Some(limit) = limit {
+        messages_query = messages_query.limit(limit + 1);
+    }
+
+    let mut messages: Vec<OwnMessage> = messages_query.fetch_all(&state.db)
         .await
-        .context("could not get messages from database")
         .map_err(AnyhowRejection)
         .map_err(warp::reject::custom)?;
 
-    messages.sort_by_key(|msg| msg.created);
-    messages.reverse();
-
     for msg in &mut messages {
-        msg.is_hidden = msg.positive_votes - msg.negative_votes < state.config.vote_threshold_hide;
+        msg.is_hidden = msg.score < threshold;
+        emote::scrub_stored(&mut msg.emote, state.config.emotes.appearance);
+    }
+
+    let mut next = None;
+    if let Some(limit) = limit {
+        if messages.len() > limit as usize {
+            messages.truncate(limit as usize);
+            next = messages.last().map(|msg| util::encode_cursor(&MessageCursor {
+                order: query.sort,
+                territory: msg.territory,
+                score: msg.score,
+                created: msg.created,
+                id: msg.id.clone(),
+            }));
+        }
     }
 
-    if version == 1 {
+    if query.v == 1 {
         return Ok(warp::reply::json(&messages));
     }
 
@@ -75,11 +106,13 @@ async fn logic(state: Arc<State>, id: i64, extra: i64, mut query: HashMap<String
     struct Mine {
         messages: Vec<OwnMessage>,
         extra: i64,
+        next: Option<String>,
     }
 
     let mine = Mine {
         messages,
         extra,
+        next,
     };
 
     Ok(warp::reply::json(&mine))
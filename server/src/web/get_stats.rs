@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use serde::Serialize;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::State;
+use crate::web::AnyhowRejection;
+
+pub fn get_stats(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::get()
+        .and(warp::path("stats"))
+        .and(warp::path("territories"))
+        .and(warp::path::end())
+        .and(super::get_scope(Arc::clone(&state)))
+        .and_then(move |scope| logic(Arc::clone(&state), scope))
+        .boxed()
+}
+
+#[derive(Serialize)]
+pub struct TerritoryStats {
+    territory: i64,
+    messages: i64,
+    average_score: f64,
+    messages_last_day: i64,
+    messages_last_week: i64,
+}
+
+async fn logic(state: Arc<State>, scope: Option<Vec<u32>>) -> Result<impl Reply, Rejection> {
+    if let Some(stats) = state.stats_cache.get(&scope) {
+        return Ok(warp::reply::json(&*stats));
+    }
+
+    let worlds = scope.as_ref().map(|worlds| serde_json::json!(worlds).to_string());
+
+    let stats = sqlx::query_as!(
+        TerritoryStats,
+        // language=sqlite
+        r#"
+            with scores as (select m.territory,
+                                   m.created,
+                                   coalesce(s.score, 0.0) as score
+                            from messages m
+                                     left join message_scores s on m.id = s.message
+                                     inner join users u on m.user = u.id
+                            where not u.shadowbanned
+                              and (?1 is null or m.world in (select value from json_each(?1))))
+            select territory                                                  as "territory!: i64",
+                   count(*)                                                   as "messages!: i64",
+                   avg(score)                                                 as "average_score!: f64",
+                   sum(created >= datetime(current_timestamp, '-1 day'))  as "messages_last_day!: i64",
+                   sum(created >= datetime(current_timestamp, '-7 days')) as "messages_last_week!: i64"
+            from scores
+            group by territory
+            order by count(*) desc"#,
+        worlds,
+    )
+        .fetch_all(&state.db)
+        .await
+        .context("could not get territory stats from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    let stats = state.stats_cache.insert(scope, stats);
+    Ok(warp::reply::json(&*stats))
+}
@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::State;
+use crate::web::AnyhowRejection;
+
+pub fn get_tokens(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::get()
+        .and(warp::path("account"))
+        .and(warp::path("tokens"))
+        .and(warp::path::end())
+        .and(super::get_token(Arc::clone(&state)))
+        .and_then(move |(id, _, token)| logic(Arc::clone(&state), id, token))
+        .boxed()
+}
+
+#[derive(Serialize)]
+pub struct AuthToken {
+    id: i64,
+    label: String,
+    created: NaiveDateTime,
+    current: bool,
+}
+
+async fn logic(state: Arc<State>, id: i64, token: i64) -> Result<impl Reply, Rejection> {
+    let tokens = sqlx::query_as!(
+        AuthToken,
+        // language=sqlite
+        r#"
+            select id,
+                   label,
+                   created,
+                   id = ? as "current!: bool"
+            from auth_tokens
+            where user = ?
+            order by created"#,
+        token,
+        id,
+    )
+        .fetch_all(&state.db)
+        .await
+        .context("could not get auth tokens from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&tokens))
+}
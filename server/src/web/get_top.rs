@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::emote::{self, EmoteData};
+use crate::message::ExportedMessage;
+use crate::State;
+use crate::web::{AnyhowRejection, WebError};
+
+pub fn get_top(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::get()
+        .and(warp::path("messages"))
+        .and(warp::path("top"))
+        .and(warp::path::end())
+        .and(warp::query::<TopQuery>())
+        .and(super::get_scope(Arc::clone(&state)))
+        .and_then(move |query, scope| logic(Arc::clone(&state), query, scope))
+        .boxed()
+}
+
+const MAX_MIN_VOTES: u32 = 100;
+
+/// A query and the world scope it was made from.
+pub type TopKey = (TopQuery, Option<Vec<u32>>);
+
+#[derive(Deserialize, Hash, PartialEq, Eq, Clone)]
+pub struct TopQuery {
+    #[serde(default)]
+    territory: Option<u32>,
+    // messages written before their pack was recorded (migration 27) have no
+    // pack and are left out when filtering by one
+    #[serde(default)]
+    pack: Option<Uuid>,
+    #[serde(default)]
+    window: TopWindow,
+    #[serde(default)]
+    min_votes: u32,
+}
+
+#[derive(Deserialize, Hash, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TopWindow {
+    Day,
+    #[default]
+    Week,
+    All,
+}
+
+async fn logic(state: Arc<State>, mut query: TopQuery, scope: Option<Vec<u32>>) -> Result<impl Reply, Rejection> {
+    // the query and scope are the cache key, so keep the number of distinct keys an
+    // anonymous caller can create small
+    query.min_votes = query.min_votes.min(MAX_MIN_VOTES);
+    if let Some(territory) = query.territory {
+        let known = state.is_known_territory(territory)
+            .await
+            .map_err(AnyhowRejection)
+            .map_err(warp::reject::custom)?;
+        if !known {
+            return Err(warp::reject::custom(WebError::InvalidTerritory));
+        }
+    }
+    if let Some(pack) = &query.pack {
+        if !state.packs.read().await.contains_key(pack) {
+            return Err(warp::reject::custom(WebError::InvalidPackId));
+        }
+    }
+
+    let key = (query, scope);
+    if let Some(top) = state.top_cache.get(&key) {
+        return Ok(warp::reply::json(&*top));
+    }
+    let (query, scope) = &key;
+
+    let territory = query.territory.map(|territory| territory as i64);
+    let pack = query.pack.map(|pack| pack.simple().to_string());
+    let worlds = scope.as_ref().map(|worlds| serde_json::json!(worlds).to_string());
+    let since = match query.window {
+        TopWindow::Day => Some(Utc::now().naive_utc() - Duration::days(1)),
+        TopWindow::Week => Some(Utc::now().naive_utc() - Duration::weeks(1)),
+        TopWindow::All => None,
+    };
+
+    // messages nobody has voted on yet have no tally
+    let mut top = sqlx::query_as!(
+        ExportedMessage,
+        // language=sqlite
+        r#"
+            select m.id,
+                   m.territory,
+                   m.world,
+                   m.ward,
+                   m.plot,
+                   m.x,
+                   m.y,
+                   m.z,
+                   m.yaw,
+                   m.message,
+                   coalesce(s.positive_votes, 0) as "positive_votes!: i32",
+                   coalesce(s.negative_votes, 0) as "negative_votes!: i32",
+                   m.glyph,
+                   m.emote as "emote: EmoteData",
+                   m.created
+            from messages m
+                     left join message_scores s on s.message = m.id
+                     inner join users u on m.user = u.id
+            where not u.shadowbanned
+              and (?1 is null or m.territory = ?1)
+              and (?2 is null or m.pack = ?2)
+              and (?3 is null or m.created >= ?3)
+              and (?6 is null or m.world in (select value from json_each(?6)))
+              and coalesce(s.positive_votes, 0) >= ?4
+              and coalesce(s.score, 0) >= ?5
+            order by coalesce(s.score, 0) desc, m.created desc
+            limit 50"#,
+        territory,
+        pack,
+        since,
+        query.min_votes,
+        state.config.vote_threshold_hide,
+        worlds,
+    )
+        .fetch_all(&state.db)
+        .await
+        .context("could not get top messages from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    for msg in &mut top {
+        emote::scrub_stored(&mut msg.emote, state.config.emotes.appearance);
+    }
+
+    let top = state.top_cache.insert(key.clone(), top);
+    Ok(warp::reply::json(&*top))
+}
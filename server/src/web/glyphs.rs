@@ -0,0 +1,19 @@
+use std::sync::Arc;
+
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::State;
+
+pub fn glyphs(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::get()
+        .and(warp::path("glyphs"))
+        .and(warp::path::end())
+        .and_then(move || logic(Arc::clone(&state)))
+        .boxed()
+}
+
+async fn logic(state: Arc<State>) -> Result<impl Reply, Rejection> {
+    let glyphs = state.glyphs.read().await;
+    Ok(warp::reply::json(&*glyphs))
+}
@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use bytes::Bytes;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::State;
+use crate::web::{AnyhowRejection, WebError};
+use crate::web::register::Credentials;
+
+pub fn recover(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::post()
+        .and(warp::path("account"))
+        .and(warp::path("recover"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(256))
+        .and(warp::body::bytes())
+        .and_then(move |code: Bytes| logic(Arc::clone(&state), code))
+        .boxed()
+}
+
+async fn logic(state: Arc<State>, bytes: Bytes) -> Result<impl Reply, Rejection> {
+    let bytes: Vec<u8> = bytes.into_iter().collect();
+    let code = String::from_utf8(bytes)
+        .context("invalid utf8 for recovery code")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+    let code_hashed = crate::util::hash(code.trim());
+
+    // recovery codes are single-use, so a fresh one is issued with the new token
+    let auth = crate::util::generate_token();
+    let hashed = crate::util::hash(&auth);
+    let recovery = crate::util::generate_token();
+    let recovery_hashed = crate::util::hash(&recovery);
+
+    let mut t = state.db.begin()
+        .await
+        .context("could not start transaction")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+    let id = sqlx::query_scalar!(
+        // language=sqlite
+        "update users set recovery = ? where recovery = ? returning id",
+        recovery_hashed,
+        code_hashed,
+    )
+        .fetch_optional(&mut *t)
+        .await
+        .context("could not recover user")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    let id = match id {
+        Some(id) => id,
+        None => return Err(warp::reject::custom(WebError::InvalidRecoveryCode)),
+    };
+
+    sqlx::query!(
+        // language=sqlite
+        "insert into auth_tokens (user, hash, label) values (?, ?, 'recovered')",
+        id,
+        hashed,
+    )
+        .execute(&mut *t)
+        .await
+        .context("could not insert auth token into database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    t.commit()
+        .await
+        .context("could not commit transaction")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    Ok(Credentials { auth, recovery: Some(recovery) }.into_response())
+}
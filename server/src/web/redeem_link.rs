@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use serde::Deserialize;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::State;
+use crate::web::{AnyhowRejection, WebError};
+
+pub fn redeem_link(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::post()
+        .and(warp::path("account"))
+        .and(warp::path("links"))
+        .and(warp::path("redeem"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024))
+        .and(warp::body::json())
+        .and_then(move |redeem: Redeem| logic(Arc::clone(&state), redeem))
+        .boxed()
+}
+
+#[derive(Deserialize)]
+pub struct Redeem {
+    code: String,
+    label: String,
+}
+
+async fn logic(state: Arc<State>, redeem: Redeem) -> Result<impl Reply, Rejection> {
+    let label = redeem.label.trim();
+    if label.is_empty() || label.chars().count() > 64 {
+        return Err(warp::reject::custom(WebError::InvalidTokenLabel));
+    }
+
+    let code_hashed = crate::util::hash(redeem.code.trim());
+    let auth = crate::util::generate_token();
+    let hashed = crate::util::hash(&auth);
+
+    let mut t = state.db.begin()
+        .await
+        .context("could not start transaction")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+    let id = sqlx::query_scalar!(
+        // language=sqlite
+        "delete from link_codes where hash = ? and expires > current_timestamp returning user",
+        code_hashed,
+    )
+        .fetch_optional(&mut *t)
+        .await
+        .context("could not redeem link code")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    let id = match id {
+        Some(id) => id,
+        None => return Err(warp::reject::custom(WebError::InvalidLinkCode)),
+    };
+
+    sqlx::query!(
+        // language=sqlite
+        "insert into auth_tokens (user, hash, label) values (?, ?, ?)",
+        id,
+        hashed,
+        label,
+    )
+        .execute(&mut *t)
+        .await
+        .context("could not insert auth token into database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    t.commit()
+        .await
+        .context("could not commit transaction")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    Ok(auth)
+}
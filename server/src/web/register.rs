@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
-use rand::distributions::{Alphanumeric, DistString};
+use serde::{Deserialize, Serialize};
 use warp::{Filter, Rejection, Reply};
 use warp::filters::BoxedFilter;
 
@@ -12,22 +12,71 @@ pub fn register(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
     warp::post()
         .and(warp::path("account"))
         .and(warp::path::end())
-        .and_then(move || logic(Arc::clone(&state)))
+        .and(warp::query::<CredentialsQuery>())
+        .and_then(move |query| logic(Arc::clone(&state), query))
         .boxed()
 }
 
-async fn logic(state: Arc<State>) -> Result<impl Reply, Rejection> {
-    let auth = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+#[derive(Deserialize)]
+pub struct CredentialsQuery {
+    #[serde(default)]
+    pub recovery: bool,
+}
+
+#[derive(Serialize)]
+pub struct Credentials {
+    pub auth: String,
+    pub recovery: Option<String>,
+}
+
+impl Credentials {
+    // clients that didn't ask for a recovery code still get a plain-text token
+    pub fn into_response(self) -> warp::reply::Response {
+        match self.recovery {
+            Some(_) => warp::reply::json(&self).into_response(),
+            None => self.auth.into_response(),
+        }
+    }
+}
+
+async fn logic(state: Arc<State>, query: CredentialsQuery) -> Result<impl Reply, Rejection> {
+    let auth = crate::util::generate_token();
     let hashed = crate::util::hash(&auth);
+    let recovery = query.recovery.then(crate::util::generate_token);
+    let recovery_hashed = recovery.as_deref().map(crate::util::hash);
+    let mut t = state.db.begin()
+        .await
+        .context("could not start transaction")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+    let id = sqlx::query_scalar!(
+        // language=sqlite
+        "insert into users (recovery, created, last_seen) values (?, current_timestamp, current_timestamp) returning id",
+        recovery_hashed,
+    )
+        .fetch_one(&mut *t)
+        .await
+        .context("could not insert user into database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
     sqlx::query!(
         // language=sqlite
-        "insert into users (auth, last_seen) values (?, current_timestamp)",
+        "insert into auth_tokens (user, hash, label) values (?, ?, 'default')",
+        id,
         hashed,
     )
-        .execute(&state.db)
+        .execute(&mut *t)
         .await
-        .context("could not insert user into database")
+        .context("could not insert auth token into database")
         .map_err(AnyhowRejection)
         .map_err(warp::reject::custom)?;
-    Ok(auth)
+
+    t.commit()
+        .await
+        .context("could not commit transaction")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    Ok(Credentials { auth, recovery }.into_response())
 }
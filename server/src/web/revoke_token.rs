@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::State;
+use crate::web::{AnyhowRejection, WebError};
+
+pub fn revoke_token(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::delete()
+        .and(warp::path("account"))
+        .and(warp::path("tokens"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(super::get_id(Arc::clone(&state)))
+        .and_then(move |token: i64, (id, _)| logic(Arc::clone(&state), id, token))
+        .boxed()
+}
+
+async fn logic(state: Arc<State>, id: i64, token: i64) -> Result<impl Reply, Rejection> {
+    // an account without tokens can't be used again, so the last one has to
+    // stay
+    let result = sqlx::query!(
+        // language=sqlite
+        r#"
+            delete from auth_tokens
+            where id = ?1
+              and user = ?2
+              and (select count(*) from auth_tokens where user = ?2) > 1"#,
+        token,
+        id,
+    )
+        .execute(&state.db)
+        .await
+        .context("could not delete auth token from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    if result.rows_affected() == 0 {
+        let exists = sqlx::query_scalar!(
+            // language=sqlite
+            "select count(*) from auth_tokens where id = ? and user = ?",
+            token,
+            id,
+        )
+            .fetch_one(&state.db)
+            .await
+            .context("could not get auth token from database")
+            .map_err(AnyhowRejection)
+            .map_err(warp::reject::custom)?;
+
+        return Err(warp::reject::custom(if exists > 0 {
+            WebError::CannotRevokeLastToken
+        } else {
+            WebError::NoSuchToken
+        }));
+    }
+
+    Ok(warp::reply())
+}
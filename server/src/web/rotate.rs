@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::State;
+use crate::web::AnyhowRejection;
+use crate::web::register::{Credentials, CredentialsQuery};
+
+pub fn rotate(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::post()
+        .and(warp::path("account"))
+        .and(warp::path("rotate"))
+        .and(warp::path::end())
+        .and(super::get_token(Arc::clone(&state)))
+        .and(warp::query::<CredentialsQuery>())
+        .and_then(move |(id, _, token), query| logic(Arc::clone(&state), id, token, query))
+        .boxed()
+}
+
+async fn logic(state: Arc<State>, id: i64, token: i64, query: CredentialsQuery) -> Result<impl Reply, Rejection> {
+    let auth = crate::util::generate_token();
+    let hashed = crate::util::hash(&auth);
+
+    let mut t = state.db.begin()
+        .await
+        .context("could not start transaction")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+    sqlx::query!(
+        // language=sqlite
+        "update auth_tokens set hash = ? where id = ?",
+        hashed,
+        token,
+    )
+        .execute(&mut *t)
+        .await
+        .context("could not rotate auth token")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    let recovery = if query.recovery {
+        let recovery = crate::util::generate_token();
+        let recovery_hashed = crate::util::hash(&recovery);
+        sqlx::query!(
+            // language=sqlite
+            "update users set recovery = ? where id = ?",
+            recovery_hashed,
+            id,
+        )
+            .execute(&mut *t)
+            .await
+            .context("could not rotate recovery code")
+            .map_err(AnyhowRejection)
+            .map_err(warp::reject::custom)?;
+
+        Some(recovery)
+    } else {
+        None
+    };
+
+    t.commit()
+        .await
+        .context("could not commit transaction")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    Ok(Credentials { auth, recovery }.into_response())
+}
@@ -6,7 +6,7 @@ use warp::{Filter, Rejection, Reply};
 use warp::filters::BoxedFilter;
 
 use crate::State;
-use crate::web::AnyhowRejection;
+use crate::web::{AnyhowRejection, WebError};
 
 pub fn vote(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
     warp::patch()
@@ -23,24 +23,68 @@ pub fn vote(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
 
 async fn logic(state: Arc<State>, id: i64, message_id: Uuid, vote: i8) -> Result<impl Reply, Rejection> {
     let message_id = message_id.simple().to_string();
-    let vote = match vote.signum() {
-        -1 => -1,
-        0 => 1,
-        1 => 1,
-        _ => unreachable!(),
-    };
+    // a vote of zero is neutral: it is recorded but counts towards neither side
+    let vote = vote.signum();
+
+    let author = sqlx::query_scalar!(
+        // language=sqlite
+        "select user from messages where id = ?",
+        message_id,
+    )
+        .fetch_optional(&state.db)
+        .await
+        .context("could not get message author from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    match author {
+        None => return Err(warp::reject::custom(WebError::NoSuchMessage)),
+        Some(author) if author == id => return Err(warp::reject::custom(WebError::CannotVoteOwnMessage)),
+        Some(_) => {}
+    }
+
+    let weight = state.vote_weight(id)
+        .await
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
     sqlx::query!(
         // language=sqlite
-        "insert into votes (user, message, vote) values (?, ?, ?) on conflict do update set vote = ?",
+        r#"
+            insert into votes (user, message, vote, weight, nullified, created)
+            values (?1, ?2, ?3, ?4, ?5, strftime('%Y-%m-%d %H:%M:%f', 'now'))
+            on conflict do update set vote      = ?3,
+                                      weight    = ?4,
+                                      nullified = ?5,
+                                      created   = case when vote = ?3 then created else excluded.created end"#,
         id,
         message_id,
         vote,
-        vote,
+        weight.weight,
+        weight.nullified,
     )
         .execute(&state.db)
         .await
         .context("could not insert vote into database")
         .map_err(AnyhowRejection)
         .map_err(warp::reject::custom)?;
+
+    sqlx::query!(
+        // language=sqlite
+        "update messages set votes_updated = strftime('%Y-%m-%d %H:%M:%f', 'now') where id = ?",
+        message_id,
+    )
+        .execute(&state.db)
+        .await
+        .context("could not update message")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    // the vote has already been recorded, so listeners missing out on it
+    // shouldn't fail the request
+    if let Err(e) = state.publish_votes(&message_id).await {
+        eprintln!("could not publish votes: {e:#?}");
+    }
+
     Ok(warp::reply())
 }
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use uuid::Uuid;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+
+use crate::State;
+use crate::web::AnyhowRejection;
+
+pub fn withdraw_vote(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
+    warp::delete()
+        .and(warp::path("messages"))
+        .and(warp::path::param())
+        .and(warp::path("votes"))
+        .and(warp::path::end())
+        .and(super::get_id(Arc::clone(&state)))
+        .and_then(move |message_id: Uuid, (id, _)| logic(Arc::clone(&state), id, message_id))
+        .boxed()
+}
+
+async fn logic(state: Arc<State>, id: i64, message_id: Uuid) -> Result<impl Reply, Rejection> {
+    let message_id = message_id.simple().to_string();
+    sqlx::query!(
+        // language=sqlite
+        "delete from votes where user = ? and message = ?",
+        id,
+        message_id,
+    )
+        .execute(&state.db)
+        .await
+        .context("could not delete vote from database")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    sqlx::query!(
+        // language=sqlite
+        "update messages set votes_updated = strftime('%Y-%m-%d %H:%M:%f', 'now') where id = ?",
+        message_id,
+    )
+        .execute(&state.db)
+        .await
+        .context("could not update message")
+        .map_err(AnyhowRejection)
+        .map_err(warp::reject::custom)?;
+
+    // the vote has already been withdrawn, so listeners missing out on it
+    // shouldn't fail the request
+    if let Err(e) = state.publish_votes(&message_id).await {
+        eprintln!("could not publish votes: {e:#?}");
+    }
+
+    Ok(warp::reply())
+}
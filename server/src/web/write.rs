@@ -1,14 +1,18 @@
+use std::f32::consts::{PI, TAU};
 use std::sync::Arc;
 
 use anyhow::Context;
+use chrono::Utc;
 use uuid::Uuid;
 use warp::{Filter, Rejection, Reply};
 use warp::filters::BoxedFilter;
 
-use crate::message::Message;
+use crate::config::{Appearance, Scope};
+use crate::emote::EmoteData;
+use crate::events::{EventKind, EventLocation};
+use crate::message::{Message, RetrievedMessage};
 use crate::pack::Template;
 use crate::State;
-use crate::util::HOUSING_ZONES;
 use crate::web::{AnyhowRejection, WebError};
 
 pub fn write(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
@@ -22,14 +26,65 @@ pub fn write(state: Arc<State>) -> BoxedFilter<(impl Reply, )> {
         .boxed()
 }
 
-async fn logic(state: Arc<State>, id: i64, extra: i64, message: Message) -> Result<impl Reply, Rejection> {
-    let housing = HOUSING_ZONES.contains(&message.territory);
-    if housing && (message.world.is_none() || message.ward.is_none()) {
-        return Err(warp::reject::custom(WebError::MissingHousingInfo));
+async fn logic(state: Arc<State>, id: i64, extra: i64, mut message: Message) -> Result<impl Reply, Rejection> {
+    let territory_info = state.territory(message.territory).await;
+    if !territory_info.writable {
+        return Err(warp::reject::custom(WebError::TerritoryNotWritable));
     }
 
-    if !housing && (message.ward.is_some() || message.plot.is_some()) {
-        return Err(warp::reject::custom(WebError::UnnecessaryHousingInfo));
+    territory_info.validate_location(message.world, message.ward.map(Into::into), message.plot.map(Into::into))
+        .map_err(warp::reject::custom)?;
+    territory_info.validate_position(message.x, message.y, message.z, message.yaw)
+        .map_err(warp::reject::custom)?;
+    // keep the yaw within -pi to pi
+    message.yaw = (message.yaw + PI).rem_euclid(TAU) - PI;
+    // open world messages are only seen by the worlds in the same scope, so
+    // they need to say which world they were written in
+    if state.config.scope != Scope::Global && message.world.is_none() {
+        return Err(warp::reject::custom(WebError::MissingWorld));
+    }
+
+    let glyph = state.glyph(message.glyph)
+        .await
+        .ok_or(WebError::InvalidGlyph)
+        .map_err(warp::reject::custom)?;
+
+    if let Some(entitlement) = &glyph.entitlement {
+        let entitled = sqlx::query_scalar!(
+            // language=sqlite
+            "select count(*) from user_entitlements where user = ? and entitlement = ?",
+            id,
+            entitlement,
+        )
+            .fetch_one(&state.db)
+            .await
+            .context("could not check entitlements")
+            .map_err(AnyhowRejection)
+            .map_err(warp::reject::custom)?;
+
+        if entitled == 0 {
+            return Err(warp::reject::custom(WebError::GlyphNotEntitled));
+        }
+    }
+
+    if let Some(emote) = &mut message.emote {
+        if !emote.is_valid() {
+            return Err(warp::reject::custom(WebError::InvalidEmote));
+        }
+
+        let allowed = state.config.emotes.allowed
+            .as_ref()
+            .map(|allowed| allowed.contains(&emote.id))
+            .unwrap_or(true);
+        if !allowed {
+            return Err(warp::reject::custom(WebError::EmoteNotAllowed));
+        }
+
+        let appearance = match state.config.emotes.appearance {
+            Appearance::Keep if message.generic_appearance => Appearance::Generic,
+            appearance => appearance,
+        };
+        emote.scrub(appearance);
     }
 
     let text = {
@@ -45,7 +100,7 @@ async fn logic(state: Arc<State>, id: i64, extra: i64, message: Message) -> Resu
         let word_1_idx = if template_1_is_list {
             message.word_1_word.map(|word| (0, word))
         } else {
-            message.word_1_list.and_then(|list| message.word_1_word.map(|word| (list, word)))
+            message.word_1_list.zip(message.word_1_word)
         };
 
         let template_2_is_list = message.template_2
@@ -55,7 +110,7 @@ async fn logic(state: Arc<State>, id: i64, extra: i64, message: Message) -> Resu
         let word_2_idx = if template_2_is_list {
             message.word_2_word.map(|word| (0, word))
         } else {
-            message.word_2_list.and_then(|list| message.word_2_word.map(|word| (list, word)))
+            message.word_2_list.zip(message.word_2_word)
         };
 
         pack.format(
@@ -69,31 +124,39 @@ async fn logic(state: Arc<State>, id: i64, extra: i64, message: Message) -> Resu
             .map_err(warp::reject::custom)?
     };
 
-    let existing = sqlx::query!(
-        // language=sqlite
-        "select count(*) as count from messages where user = ?",
-        id
-    )
-        .fetch_one(&state.db)
-        .await
-        .context("could not get count of messages")
-        .map_err(AnyhowRejection)
-        .map_err(warp::reject::custom)?;
-
-    if existing.count >= state.config.max_messages + extra as i32 {
-        return Err(warp::reject::custom(WebError::TooManyMessages));
-    }
-
     let message_id = Uuid::new_v4().simple().to_string();
     let territory = message.territory as i64;
+    let pack_id = message.pack_id.simple().to_string();
+    let max_messages = state.config.max_messages + extra as i32;
+    let min_spacing = state.config.min_spacing.powi(2);
+    // housing areas are per world, while open world messages are shown to
+    // every world in the same scope and so can stack from any of them
+    let housing = territory_info.is_housing();
+    let worlds = if housing {
+        None
+    } else {
+        state.world_scope(message.world).await
+    };
+    let worlds = worlds.map(|worlds| serde_json::json!(worlds).to_string());
 
-    let json = serde_json::to_string(&message.emote)
-        .context("could not serialise emote")
-        .map_err(AnyhowRejection)
-        .map_err(warp::reject::custom)?;
-    sqlx::query!(
+    // the limits are checked in the insert itself so that concurrent writes
+    // can't both pass them
+    let emote = message.emote.as_ref().map(EmoteData::to_bytes);
+    let inserted = sqlx::query!(
         // language=sqlite
-        "insert into messages (id, user, territory, world, ward, plot, x, y, z, yaw, message, glyph, emote) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        r#"
+            insert into messages (id, user, territory, world, ward, plot, x, y, z, yaw, message, glyph, emote, pack)
+            select ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14
+            where (select count(*) from messages where user = ?2) < ?15
+              and not exists(select 1
+                             from messages
+                             where user = ?2
+                               and territory = ?3
+                               and (not ?16 or world is ?4)
+                               and (?18 is null or world in (select value from json_each(?18)))
+                               and ward is ?5
+                               and plot is ?6
+                               and (x - ?7) * (x - ?7) + (y - ?8) * (y - ?8) + (z - ?9) * (z - ?9) < ?17)"#,
         message_id,
         id,
         territory,
@@ -106,12 +169,72 @@ async fn logic(state: Arc<State>, id: i64, extra: i64, message: Message) -> Resu
         message.yaw,
         text,
         message.glyph,
-        json,
+        emote,
+        pack_id,
+        max_messages,
+        housing,
+        min_spacing,
+        worlds,
     )
         .execute(&state.db)
         .await
         .context("could not insert message into database")
         .map_err(AnyhowRejection)
         .map_err(warp::reject::custom)?;
+
+    if inserted.rows_affected() == 0 {
+        let existing = sqlx::query_scalar!(
+            // language=sqlite
+            "select count(*) from messages where user = ?",
+            id,
+        )
+            .fetch_one(&state.db)
+            .await
+            .context("could not get count of messages")
+            .map_err(AnyhowRejection)
+            .map_err(warp::reject::custom)?;
+
+        return Err(warp::reject::custom(if existing >= max_messages {
+            WebError::TooManyMessages
+        } else {
+            WebError::TooCloseToOwnMessage
+        }));
+    }
+
+    // writing messages counts towards an account being established
+    if let Err(e) = state.update_vote_weights(id).await {
+        eprintln!("could not update vote weights: {e:#?}");
+    }
+
+    let location = EventLocation {
+        territory,
+        world: message.world.map(Into::into),
+        ward: message.ward.map(Into::into),
+        plot: message.plot.map(Into::into),
+    };
+    let added = EventKind::MessageAdded {
+        message: Box::new(RetrievedMessage {
+            id: message_id.clone(),
+            x: message.x.into(),
+            y: message.y.into(),
+            z: message.z.into(),
+            yaw: message.yaw.into(),
+            message: text,
+            positive_votes: 0,
+            negative_votes: 0,
+            user_vote: 0,
+            glyph: message.glyph.into(),
+            emote: message.emote,
+            score: 0.0,
+            created: Utc::now().naive_utc(),
+            user: id,
+            last_seen_minutes: 0,
+            votes_updated: None,
+        }),
+    };
+    if let Err(e) = state.publish_from(id, location, added).await {
+        eprintln!("could not publish message: {e:#?}");
+    }
+
     Ok(message_id)
 }
@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::config::Scope;
+use crate::State;
+
+#[derive(Debug, Deserialize)]
+pub struct DataCenter {
+    pub data_center: String,
+    pub region: String,
+    pub worlds: BTreeMap<u32, String>,
+}
+
+impl State {
+    pub async fn update_worlds(&self) -> Result<()> {
+        let text = tokio::fs::read_to_string(&self.config.worlds)
+            .await
+            .with_context(|| format!("could not read worlds at {:?}", self.config.worlds))?;
+        let data_centers: Vec<DataCenter> = serde_yaml::from_str(&text)
+            .context("could not parse worlds")?;
+
+        println!("added {} data centers", data_centers.len());
+        *self.worlds.write().await = data_centers;
+
+        Ok(())
+    }
+
+    pub async fn is_known_world(&self, world: u32) -> bool {
+        self.worlds.read()
+            .await
+            .iter()
+            .any(|dc| dc.worlds.contains_key(&world))
+    }
+
+    /// Gets the worlds that share open world messages with the given world,
+    /// or `None` if messages are shared between all worlds. Requests that
+    /// don't say which world they are from see messages from every world.
+    pub async fn world_scope(&self, world: Option<u32>) -> Option<Vec<u32>> {
+        if self.config.scope == Scope::Global {
+            return None;
+        }
+
+        let world = world?;
+        let data_centers = self.worlds.read().await;
+        let home = data_centers.iter()
+            .find(|dc| dc.worlds.contains_key(&world));
+
+        let worlds = match (self.config.scope, home) {
+            (Scope::DataCenter, Some(home)) => home.worlds.keys().copied().collect(),
+            (Scope::Region, Some(home)) => data_centers.iter()
+                .filter(|dc| dc.region == home.region)
+                .flat_map(|dc| dc.worlds.keys().copied())
+                .collect(),
+            _ => vec![world],
+        };
+
+        Some(worlds)
+    }
+}